@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use clap::Parser;
+use escvpnet::gateway::{router, Gateway};
+
+/// HTTP gateway to ESC/VP.net projectors
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Address to serve HTTP on
+    #[arg(
+        short,
+        long,
+        default_value = "127.0.0.1:8080",
+        env = "ESCVPNET_GATEWAY_LISTEN"
+    )]
+    listen: String,
+    /// ESC/VP.net password of the projectors
+    #[arg(short, long, env = "ESCVPNET_PASSWORD")]
+    password: Option<String>,
+    /// Timeout in milliseconds
+    #[arg(short, long, default_value = "5000")]
+    timeout: u64,
+    /// Address to broadcast discovery from
+    #[arg(long, default_value = "0.0.0.0:0")]
+    bind: String,
+    /// Address to broadcast discovery to
+    #[arg(long, default_value = "255.255.255.255:3629")]
+    broadcast: String,
+    /// Interval in seconds of polling the discovered projectors for `/metrics`
+    #[cfg(feature = "metrics")]
+    #[arg(long, default_value = "30")]
+    metrics_interval: u64,
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    let timeout = Duration::from_millis(cli.timeout);
+    let gateway = Gateway::new(
+        cli.password,
+        timeout,
+        cli.bind.clone(),
+        cli.broadcast.clone(),
+    );
+    // Polls through the gateway's connections, as projectors only accept one session
+    #[cfg(feature = "metrics")]
+    let metrics = {
+        let mut exporter = escvpnet::metrics::Exporter::with_connections(gateway.connections());
+        let projectors =
+            escvpnet::client::Client::discover(cli.bind.as_str(), cli.broadcast.as_str(), timeout)
+                .await
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))?;
+        for projector in &projectors {
+            eprintln!("Discovered {}", projector.addr());
+            exporter.add_discovered(projector);
+        }
+        let exporter = std::sync::Arc::new(exporter);
+        let interval = Duration::from_secs(cli.metrics_interval);
+        tokio::spawn({
+            let exporter = exporter.clone();
+            async move { exporter.run(interval).await }
+        });
+        escvpnet::metrics::router(exporter)
+    };
+
+    let app = router(gateway);
+    #[cfg(feature = "metrics")]
+    let app = app.merge(metrics);
+    let listener = tokio::net::TcpListener::bind(&cli.listen).await?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await
+}
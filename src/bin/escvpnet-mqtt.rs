@@ -0,0 +1,94 @@
+use std::{net::SocketAddr, time::Duration};
+
+use clap::Parser;
+use escvpnet::{
+    client::{resolve, Client},
+    mqtt::Bridge,
+};
+use rumqttc::MqttOptions;
+
+/// Bridge ESC/VP.net projectors to MQTT, with Home Assistant discovery
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// MQTT broker host
+    #[arg(long, default_value = "localhost", env = "ESCVPNET_MQTT_HOST")]
+    mqtt_host: String,
+    /// MQTT broker port
+    #[arg(long, default_value = "1883", env = "ESCVPNET_MQTT_PORT")]
+    mqtt_port: u16,
+    /// MQTT username
+    #[arg(long, env = "ESCVPNET_MQTT_USERNAME")]
+    mqtt_username: Option<String>,
+    /// MQTT password
+    #[arg(long, env = "ESCVPNET_MQTT_PASSWORD", requires = "mqtt_username")]
+    mqtt_password: Option<String>,
+    /// MQTT client id
+    #[arg(long, default_value = "escvpnet")]
+    client_id: String,
+    /// Prefix of state and command topics
+    #[arg(long, default_value = "escvpnet")]
+    topic_prefix: String,
+    /// Home Assistant discovery prefix
+    #[arg(long, default_value = "homeassistant")]
+    discovery_prefix: String,
+    /// ESC/VP.net password of the projectors
+    #[arg(short, long, env = "ESCVPNET_PASSWORD")]
+    password: Option<String>,
+    /// Projector to bridge, as `host` or `host:port`, in addition to the discovered ones
+    #[arg(long = "projector", value_parser = parse_projector)]
+    projectors: Vec<(String, SocketAddr)>,
+    /// Do not discover projectors
+    #[arg(long)]
+    no_discovery: bool,
+    /// Address to broadcast discovery from
+    #[arg(long, default_value = "0.0.0.0:0")]
+    bind: String,
+    /// Address to broadcast discovery to
+    #[arg(long, default_value = "255.255.255.255:3629")]
+    broadcast: String,
+    /// Polling interval in seconds
+    #[arg(short, long, default_value = "10")]
+    interval: u64,
+    /// Timeout in milliseconds
+    #[arg(short, long, default_value = "5000")]
+    timeout: u64,
+}
+
+/// Parses `host[:port]`, naming the projector after the host
+fn parse_projector(value: &str) -> Result<(String, SocketAddr), String> {
+    let addr = resolve(value).map_err(|error| error.to_string())?;
+    Ok((value.to_string(), addr))
+}
+
+#[tokio::main]
+async fn main() -> escvpnet::Result<()> {
+    let cli = Cli::parse();
+    let timeout = Duration::from_millis(cli.timeout);
+
+    let mut options = MqttOptions::new(cli.client_id, cli.mqtt_host, cli.mqtt_port);
+    if let Some(username) = cli.mqtt_username {
+        options.set_credentials(username, cli.mqtt_password.unwrap_or_default());
+    }
+    let mut bridge = Bridge::new(
+        options,
+        cli.password,
+        timeout,
+        Duration::from_secs(cli.interval),
+    );
+    bridge.set_topic_prefix(cli.topic_prefix);
+    bridge.set_discovery_prefix(cli.discovery_prefix);
+
+    for (name, addr) in cli.projectors {
+        bridge.add(addr, name);
+    }
+    if !cli.no_discovery {
+        let projectors =
+            Client::discover(cli.bind.as_str(), cli.broadcast.as_str(), timeout).await?;
+        for projector in &projectors {
+            eprintln!("Discovered {}", projector.addr());
+            bridge.add_discovered(projector);
+        }
+    }
+    bridge.run().await
+}
@@ -0,0 +1,81 @@
+use std::{net::SocketAddr, time::Duration};
+
+use clap::Parser;
+use escvpnet::{
+    client::{resolve, Client},
+    osc::Bridge,
+};
+use tokio::net::UdpSocket;
+
+/// Control ESC/VP.net projectors with Open Sound Control
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Address to receive OSC messages on
+    #[arg(
+        short,
+        long,
+        default_value = "0.0.0.0:9000",
+        env = "ESCVPNET_OSC_LISTEN"
+    )]
+    listen: String,
+    /// ESC/VP.net password of the projectors
+    #[arg(short, long, env = "ESCVPNET_PASSWORD")]
+    password: Option<String>,
+    /// Projector to control, as `name=host` or `name=host:port`, in addition to the discovered ones
+    #[arg(long = "projector", value_parser = parse_projector)]
+    projectors: Vec<(String, SocketAddr)>,
+    /// Address to send state changes to, in addition to the senders of messages
+    #[arg(long = "peer")]
+    peers: Vec<SocketAddr>,
+    /// Do not discover projectors
+    #[arg(long)]
+    no_discovery: bool,
+    /// Address to broadcast discovery from
+    #[arg(long, default_value = "0.0.0.0:0")]
+    bind: String,
+    /// Address to broadcast discovery to
+    #[arg(long, default_value = "255.255.255.255:3629")]
+    broadcast: String,
+    /// Polling interval in seconds
+    #[arg(short, long, default_value = "5")]
+    interval: u64,
+    /// Timeout in milliseconds
+    #[arg(short, long, default_value = "5000")]
+    timeout: u64,
+}
+
+/// Parses `name=host[:port]`, resolving the host to its first address
+fn parse_projector(value: &str) -> Result<(String, SocketAddr), String> {
+    let (name, addr) = value
+        .split_once('=')
+        .ok_or_else(|| "expected name=host[:port]".to_string())?;
+    let addr = resolve(addr).map_err(|error| error.to_string())?;
+    Ok((name.to_string(), addr))
+}
+
+#[tokio::main]
+async fn main() -> escvpnet::Result<()> {
+    let cli = Cli::parse();
+    let timeout = Duration::from_millis(cli.timeout);
+
+    let mut bridge = Bridge::new(cli.password, timeout, Duration::from_secs(cli.interval));
+    for (name, addr) in &cli.projectors {
+        let name = bridge.add(*addr, name);
+        eprintln!("Controlling {addr} as /projector/{name}");
+    }
+    for peer in cli.peers {
+        bridge.add_peer(peer);
+    }
+    if !cli.no_discovery {
+        let projectors =
+            Client::discover(cli.bind.as_str(), cli.broadcast.as_str(), timeout).await?;
+        for projector in &projectors {
+            let name = bridge.add_discovered(projector);
+            eprintln!("Discovered {} as /projector/{name}", projector.addr());
+        }
+    }
+    let socket = UdpSocket::bind(&cli.listen).await?;
+    eprintln!("Listening on udp://{}", socket.local_addr()?);
+    bridge.run(socket).await
+}
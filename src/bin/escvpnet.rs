@@ -0,0 +1,225 @@
+use std::{process::ExitCode, time::Duration};
+
+use clap::{error::ErrorKind as ClapErrorKind, CommandFactory, Parser, Subcommand};
+use escvpnet::{
+    client::{with_default_port, Client},
+    command::Command,
+    commands::{Power, SelectSource, Source, Switch},
+    control::ProjectorControl,
+    error::ErrorKind,
+    packet::Status,
+};
+use serde_json::{json, Value};
+
+/// Control ESC/VP.net projectors
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Projector address, as `host` or `host:port`
+    #[arg(short, long, global = true, env = "ESCVPNET_ADDRESS")]
+    address: Option<String>,
+    /// ESC/VP.net password
+    #[arg(short, long, global = true, env = "ESCVPNET_PASSWORD")]
+    password: Option<String>,
+    /// Timeout in milliseconds
+    #[arg(short, long, global = true, default_value = "5000")]
+    timeout: u64,
+    /// Print JSON instead of human readable output
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Broadcast a Hello packet and list the projectors answering
+    Discover {
+        #[arg(long, default_value = "0.0.0.0:0")]
+        bind: String,
+        #[arg(long, default_value = "255.255.255.255:3629")]
+        broadcast: String,
+    },
+    /// Query a command, e.g. `get LAMP`
+    Get { name: String },
+    /// Set a command, e.g. `set SOURCE 30` or `set NWPNAME "Room 101"`
+    Set {
+        name: String,
+        #[arg(required = true)]
+        values: Vec<String>,
+    },
+    /// Turn the projector on or off
+    Power { state: Switch },
+    /// Select the input source, by name (`hdmi1`) or code (`30`)
+    Source { source: Source },
+    /// Show power state, source, lamp hours and fault
+    Status,
+    /// Change the ESC/VP.net password, removing it when no new password is given
+    Passwd { new_password: Option<String> },
+    /// Print Prometheus metrics of the projector, or of the discovered projectors without
+    /// `--address`, such as for the textfile collector of node_exporter
+    #[cfg(feature = "metrics")]
+    Metrics {
+        #[arg(long, default_value = "0.0.0.0:0")]
+        bind: String,
+        #[arg(long, default_value = "255.255.255.255:3629")]
+        broadcast: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+    match run(cli).await {
+        Ok(Some(output)) => {
+            print(&output, json);
+            ExitCode::SUCCESS
+        }
+        Ok(None) => ExitCode::SUCCESS,
+        Err(error) => {
+            if json {
+                println!(
+                    "{}",
+                    json!({ "error": error.kind().to_string(), "message": error.message() })
+                );
+            } else {
+                eprintln!("{error}");
+            }
+            ExitCode::from(exit_code(&error.kind()))
+        }
+    }
+}
+
+async fn run(cli: Cli) -> escvpnet::Result<Option<Value>> {
+    let timeout = Duration::from_millis(cli.timeout);
+    let Cli {
+        address,
+        password,
+        action,
+        ..
+    } = cli;
+    match action {
+        Action::Discover { bind, broadcast } => {
+            let projectors = Client::discover(bind.as_str(), broadcast.as_str(), timeout).await?;
+            Ok(Some(Value::Array(
+                projectors.iter().map(escvpnet::json::projector).collect(),
+            )))
+        }
+        #[cfg(feature = "metrics")]
+        Action::Metrics { bind, broadcast } => {
+            let mut exporter = escvpnet::metrics::Exporter::new(password, timeout);
+            match address {
+                Some(address) => {
+                    for addr in tokio::net::lookup_host(with_default_port(&address))
+                        .await?
+                        .take(1)
+                    {
+                        exporter.add(addr, address.clone());
+                    }
+                }
+                None => {
+                    for projector in
+                        Client::discover(bind.as_str(), broadcast.as_str(), timeout).await?
+                    {
+                        exporter.add_discovered(&projector);
+                    }
+                }
+            }
+            exporter.poll().await;
+            print!("{}", exporter.encode());
+            Ok(None)
+        }
+        Action::Passwd { new_password } => {
+            Client::change_password(address_or_exit(address), password, new_password, timeout)
+                .await?;
+            Ok(None)
+        }
+        Action::Get { name } => {
+            let mut client = connect(address, password, timeout).await?;
+            let response = client.execute(Command::Get { name }).await?;
+            Ok(response
+                .name()
+                .map(|name| json!({ name: response.value() })))
+        }
+        Action::Set { name, values } => {
+            let mut client = connect(address, password, timeout).await?;
+            client.execute(Command::Set { name, values }).await?;
+            Ok(None)
+        }
+        Action::Power { state } => {
+            let mut client = connect(address, password, timeout).await?;
+            client.set(Power, state).await?;
+            Ok(None)
+        }
+        Action::Source { source } => {
+            let mut client = connect(address, password, timeout).await?;
+            client.set(SelectSource, source).await?;
+            Ok(None)
+        }
+        Action::Status => {
+            let mut client = connect(address, password, timeout).await?;
+            Ok(Some(escvpnet::json::status(&client.status().await?)))
+        }
+    }
+}
+
+async fn connect(
+    address: Option<String>,
+    password: Option<String>,
+    timeout: Duration,
+) -> escvpnet::Result<Client> {
+    Client::connect(address_or_exit(address), password, timeout).await
+}
+
+/// Appends the default port to addresses without one, exiting when there is none
+fn address_or_exit(address: Option<String>) -> String {
+    let Some(address) = address else {
+        Cli::command()
+            .error(
+                ClapErrorKind::MissingRequiredArgument,
+                "a projector address is required, use --address",
+            )
+            .exit()
+    };
+    with_default_port(&address)
+}
+
+fn print(output: &Value, json: bool) {
+    if json {
+        println!("{output}");
+        return;
+    }
+    match output {
+        Value::Array(items) => items.iter().for_each(|item| print(item, false)),
+        Value::Object(fields) => {
+            let line: Vec<String> = fields
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| match value {
+                    Value::String(value) => format!("{key}={value}"),
+                    value => format!("{key}={value}"),
+                })
+                .collect();
+            println!("{}", line.join(" "));
+        }
+        Value::String(value) => println!("{value}"),
+        value => println!("{value}"),
+    }
+}
+
+/// Exit codes following `sysexits.h`
+fn exit_code(kind: &ErrorKind) -> u8 {
+    use std::io::ErrorKind as Io;
+    match kind {
+        ErrorKind::Command | ErrorKind::Power(_) => 1,
+        ErrorKind::Fault(_) => 3,
+        ErrorKind::Decoding => 65,
+        ErrorKind::Encoding => 64,
+        ErrorKind::IO(Io::TimedOut | Io::ConnectionRefused) => 69,
+        ErrorKind::IO(_) => 74,
+        ErrorKind::Protocol(Status::Unauthorized | Status::Forbidden) => 77,
+        ErrorKind::Protocol(_) => 76,
+        _ => 70,
+    }
+}
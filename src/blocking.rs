@@ -0,0 +1,244 @@
+//! Blocking client built on `std::net`, for programs without an async runtime
+//!
+//! Packets and commands are encoded with the same [`DecodeFrom`] and [`EncodeTo`]
+//! implementations as the async client, on in-memory buffers which never wait.
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    pin::Pin,
+    time::Duration,
+};
+
+use futures_util::FutureExt;
+
+use crate::{
+    client::{
+        check_response, connect_packet, fault_error, password_packet, password_result,
+        query_output, Projector, HELLO_PACKET,
+    },
+    command::{prompt_position, Command, Response},
+    commands::{Control, ErrorCode, Query},
+    error::ErrorKind,
+    header::Header,
+    io::{DecodeFrom, EncodeTo, Length},
+    packet::Packet,
+    Result,
+};
+
+const BUF_SIZE: usize = 1024;
+const PACKET_HEADER_LENGTH: usize = 16;
+
+pub struct Client {
+    stream: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub fn discover<A: ToSocketAddrs>(
+        bind_addr: A,
+        broadcast_addr: A,
+        timeout: Duration,
+    ) -> Result<Vec<Projector>> {
+        let socket = UdpSocket::bind(bind_addr)?;
+
+        socket.set_broadcast(true)?;
+        socket.set_read_timeout(Some(timeout))?;
+
+        socket.send_to(&HELLO_PACKET, broadcast_addr)?;
+        let mut projectors = Vec::new();
+        let mut buf = [0; BUF_SIZE];
+
+        while let Ok((n, addr)) = socket.recv_from(&mut buf) {
+            let Ok(packet) = decode(&buf[..n]) else {
+                continue;
+            };
+            projectors.push(Projector::from_hello(addr, buf[10], &packet))
+        }
+        Ok(projectors)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        password: Option<String>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let mut client = Self::open(addr, timeout)?;
+        client.write_packet(connect_packet(password)?)?;
+        client.read_packet()?.status_as_result()?;
+        Ok(client)
+    }
+
+    /// Changes the ESC/VP.net password of a projector, a `new_password` of `None` removing it
+    pub fn change_password<A: ToSocketAddrs>(
+        addr: A,
+        password: Option<String>,
+        new_password: Option<String>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut client = Self::open(addr, timeout)?;
+        client.write_packet(password_packet(password, new_password)?)?;
+        password_result(client.read_packet()?.status)
+    }
+
+    fn open<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Self> {
+        let mut last_error = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    // Every read and write is limited, like the exchanges of the async client
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(Self {
+                        stream: BufReader::new(stream),
+                    });
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.map(io_error).unwrap_or_else(|| {
+            crate::Error::new(
+                ErrorKind::IO(std::io::ErrorKind::InvalidInput),
+                "No address to connect to".to_string(),
+            )
+        }))
+    }
+
+    pub fn send_packet(&mut self, packet: Packet) -> Result<Packet> {
+        self.write_packet(packet)?;
+        self.read_packet()
+    }
+
+    /// Sends an ESC/VP21 command and waits for the projector's `:` prompt.
+    ///
+    /// Returns a [`Response::Value`] for a [`Command::Get`], and [`Response::Acknowledged`]
+    /// for a [`Command::Set`]. A reply of `ERR` is an error of kind [`ErrorKind::Command`],
+    /// or of kind [`ErrorKind::Fault`] when the projector reports a fault.
+    pub fn execute(&mut self, command: Command) -> Result<Response> {
+        match self.exchange(command) {
+            Err(error) if matches!(error.kind(), ErrorKind::Command) => {
+                match self.exchange(ErrorCode.get()) {
+                    Ok(response) => Err(fault_error(response).unwrap_or(error)),
+                    Err(_) => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn exchange(&mut self, command: Command) -> Result<Response> {
+        let is_get = matches!(command, Command::Get { .. });
+        let data = encode(command)?;
+        self.stream.get_mut().write_all(&data).map_err(io_error)?;
+
+        let mut reply = Vec::new();
+        loop {
+            if self.stream.read_until(b':', &mut reply).map_err(io_error)? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            if let Some(prompt) = prompt_position(&reply) {
+                reply.truncate(prompt);
+                return check_response(is_get, String::from_utf8(reply)?.parse()?);
+            }
+        }
+    }
+
+    /// Queries a typed command from [`crate::commands`]
+    pub fn get<Q: Query>(&mut self, query: Q) -> Result<Q::Output> {
+        query_output::<Q>(self.execute(query.get())?)
+    }
+
+    /// Sets a typed command from [`crate::commands`]
+    pub fn set<C: Control>(&mut self, control: C, input: C::Input) -> Result<()> {
+        self.execute(control.set(input))?;
+        Ok(())
+    }
+
+    fn write_packet(&mut self, packet: Packet) -> Result<()> {
+        let data = encode(packet)?;
+        self.stream.get_mut().write_all(&data).map_err(io_error)
+    }
+
+    fn read_packet(&mut self) -> Result<Packet> {
+        let mut data = vec![0; PACKET_HEADER_LENGTH];
+        self.stream.read_exact(&mut data).map_err(io_error)?;
+        let headers = data[PACKET_HEADER_LENGTH - 1] as usize;
+        data.resize(PACKET_HEADER_LENGTH + headers * Header::LENGTH, 0);
+        self.stream
+            .read_exact(&mut data[PACKET_HEADER_LENGTH..])
+            .map_err(io_error)?;
+        decode(&data)
+    }
+}
+
+/// Error of the async client for the same failure, an elapsed read or write timeout
+/// being reported as `WouldBlock` on some platforms
+fn io_error(error: std::io::Error) -> crate::Error {
+    match error.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => crate::Error::new(
+            ErrorKind::IO(std::io::ErrorKind::TimedOut),
+            "Timed out".to_string(),
+        ),
+        _ => error.into(),
+    }
+}
+
+fn encode<E: EncodeTo<Vec<u8>, Error = crate::Error>>(value: E) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    value
+        .encode_to(&mut Pin::new(&mut data))
+        .now_or_never()
+        .expect("encoding to memory never waits")?;
+    Ok(data)
+}
+
+fn decode<D: for<'a> DecodeFrom<&'a [u8], Error = crate::Error>>(mut data: &[u8]) -> Result<D> {
+    D::decode_from(&mut Pin::new(&mut data))
+        .now_or_never()
+        .expect("decoding from memory never waits")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::testing::{silent_server, spawn_server};
+
+    #[tokio::test]
+    async fn blocking_client() {
+        let (addr, _) = spawn_server(&[("PWR", "01")], None).await;
+
+        tokio::task::spawn_blocking(move || {
+            let mut client = Client::connect(addr, None, Duration::from_secs(1)).unwrap();
+            let response = client.execute("PWR?".parse().unwrap()).unwrap();
+            assert_eq!(response.value(), Some("01"));
+            assert_eq!(
+                client.execute("PWR ON".parse().unwrap()).unwrap(),
+                Response::Acknowledged
+            );
+            assert!(matches!(
+                client.execute("LAMP?".parse().unwrap()).unwrap_err().kind(),
+                ErrorKind::Command
+            ));
+        })
+        .await
+        .unwrap();
+    }
+    #[tokio::test]
+    async fn silent_projector() {
+        let addr = silent_server().await;
+
+        tokio::task::spawn_blocking(move || {
+            let timeout = Duration::from_millis(100);
+            let error = Client::connect(addr, None, timeout).err().unwrap();
+            assert!(matches!(
+                error.kind(),
+                ErrorKind::IO(std::io::ErrorKind::TimedOut)
+            ));
+            let error = Client::change_password(addr, None, None, timeout).unwrap_err();
+            assert!(matches!(
+                error.kind(),
+                ErrorKind::IO(std::io::ErrorKind::TimedOut)
+            ));
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -1,18 +1,22 @@
-use std::{net::SocketAddr, pin::Pin, time::Duration};
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    time::Duration,
+};
 
 use crate::{
+    command::{Command, Response},
+    commands::{Control, ErrorCode, Fault, Query},
+    connection::{Connection, Transport},
+    error::ErrorKind,
     header::{Header, HeaderIdentifier},
-    io::{DecodeFrom, EncodeTo},
+    io::DecodeFrom,
     packet::{Packet, PacketCategory, Status},
     Result,
 };
-use tokio::{
-    io::AsyncWriteExt,
-    io::{BufReader, BufWriter},
-    net::{TcpStream, ToSocketAddrs, UdpSocket},
-};
+use tokio::net::{TcpStream, ToSocketAddrs, UdpSocket};
 
-const HELLO_PACKET: [u8; 16] = [
+pub(crate) const HELLO_PACKET: [u8; 16] = [
     b'E', b'S', b'C', b'/', b'V', b'P', b'.', b'n', b'e', b't', // Protocol Header
     0x10, // Protocol version
     1,    // Type identifier
@@ -22,14 +26,19 @@ const HELLO_PACKET: [u8; 16] = [
 ];
 const BUF_SIZE: usize = 1024;
 
+/// TCP and UDP port of ESC/VP.net
+pub const DEFAULT_PORT: u16 = 3629;
+
 const CONNECT_PACKET: Packet = Packet {
     category: PacketCategory::Connect,
     status: Status::Null,
     headers: vec![],
 };
 
-pub struct Client {
-    stream: TcpStream,
+/// ESC/VP21 client, over ESC/VP.net by default and over any [`Transport`] with
+/// [`Client::handshake`] or [`Client::raw`]
+pub struct Client<S = TcpStream> {
+    connection: Connection<S>,
 }
 
 impl Client {
@@ -52,79 +61,452 @@ impl Client {
                 Err(_) => return Ok(projectors),
             }
         {
-            let packet = Packet::decode_from(&mut Pin::new(&mut &buf[..n])).await?; // handle result
-            let name = packet
-                .headers
-                .iter()
-                .find(|h| matches!(h.identifier(), HeaderIdentifier::ProjectorName))
-                .map(|h| h.information().to_string());
-            projectors.push(Projector { addr, name })
+            let Ok(packet) = Packet::decode_from(&mut Pin::new(&mut &buf[..n])).await else {
+                continue; // malformed replies are reported by `discovery::Discovery`
+            };
+            projectors.push(Projector::from_hello(addr, buf[10], &packet))
         }
         Ok(projectors)
     }
 
+    /// Connects and runs the ESC/VP.net handshake, `timeout` limiting each of them
     pub async fn connect<A: ToSocketAddrs>(
         addr: A,
         password: Option<String>,
         timeout: Duration,
     ) -> Result<Self> {
-        let mut stream = tokio::time::timeout(timeout, TcpStream::connect(addr))
+        let connection = Self::open(addr, timeout).await?;
+        let handshake = Self::handshake(connection.into_inner(), password);
+        tokio::time::timeout(timeout, handshake)
             .await
-            .map_err(|_| {
-                crate::Error::new(
-                    crate::error::ErrorKind::IO(std::io::ErrorKind::TimedOut),
-                    "Timed out".to_string(),
-                )
-            })??;
-        {
-            let (reader, writer) = stream.split();
-            let mut writer = BufWriter::new(writer);
-            let mut pinned_writer = Pin::new(&mut writer);
-            let mut reader = BufReader::new(reader);
-            let mut pinned_reader = Pin::new(&mut reader);
-
-            let packet = match password {
-                None => CONNECT_PACKET,
-                Some(password) => {
-                    let mut packet = CONNECT_PACKET;
-                    packet
-                        .headers
-                        .push(Header::new(HeaderIdentifier::Password, 1, password)?);
-                    packet
-                }
-            };
+            .map_err(|_| timed_out())?
+    }
+
+    /// Changes the ESC/VP.net password of a projector, a `new_password` of `None` removing it
+    pub async fn change_password<A: ToSocketAddrs>(
+        addr: A,
+        password: Option<String>,
+        new_password: Option<String>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut connection = Self::open(addr, timeout).await?;
 
-            packet.encode_to(&mut pinned_writer).await?;
-            writer.flush().await?;
-            Packet::decode_from(&mut pinned_reader)
-                .await?
-                .status_as_result()?;
+        let exchange = async {
+            connection
+                .write_packet(password_packet(password, new_password)?)
+                .await?;
+            connection.read_packet().await
+        };
+        let reply = tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| timed_out())??;
+        password_result(reply.status)
+    }
+
+    async fn open<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Connection<TcpStream>> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect(addr))
+            .await
+            .map_err(|_| timed_out())??;
+        Ok(Connection::new(stream))
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Client<tokio_serial::SerialStream> {
+    /// Opens a serial port with the ESC/VP21 settings, 8 data bits, no parity, 1 stop bit
+    /// and no flow control, usually at 9600 bauds
+    pub fn open_serial(path: &str, baud_rate: u32) -> Result<Self> {
+        let builder = tokio_serial::new(path, baud_rate)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .flow_control(tokio_serial::FlowControl::None);
+        let stream = tokio_serial::SerialStream::open(&builder).map_err(std::io::Error::from)?;
+        Ok(Self::raw(stream))
+    }
+}
+
+impl<S: Transport> Client<S> {
+    /// Runs the ESC/VP.net `Connect` handshake on an open stream
+    pub async fn handshake(stream: S, password: Option<String>) -> Result<Self> {
+        let mut connection = Connection::new(stream);
+        connection.write_packet(connect_packet(password)?).await?;
+        connection.read_packet().await?.status_as_result()?;
+        Ok(Self { connection })
+    }
+
+    /// Sends ESC/VP21 commands without ESC/VP.net handshake, as on a serial port
+    pub fn raw(stream: S) -> Self {
+        Self {
+            connection: Connection::new(stream),
         }
-        Ok(Self { stream })
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.connection.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.connection.into_inner()
     }
 
     pub async fn send_packet(&mut self, packet: Packet) -> Result<Packet> {
-        let (reader, writer) = self.stream.split();
-        let mut writer = BufWriter::new(writer);
-        let mut pinned_writer = Pin::new(&mut writer);
-        let mut reader = BufReader::new(reader);
-        let mut pinned_reader = Pin::new(&mut reader);
-        packet.encode_to(&mut pinned_writer).await?;
+        self.connection.write_packet(packet).await?;
+        self.connection.read_packet().await
+    }
+
+    /// Sends an ESC/VP21 command and waits for the projector's `:` prompt.
+    ///
+    /// Returns a [`Response::Value`] for a [`Command::Get`], and [`Response::Acknowledged`]
+    /// for a [`Command::Set`]. A reply of `ERR` is an error of kind [`ErrorKind::Command`],
+    /// or of kind [`ErrorKind::Fault`] when the projector reports a fault.
+    pub async fn execute(&mut self, command: Command) -> Result<Response> {
+        match self.exchange(command).await {
+            Err(error) if matches!(error.kind(), ErrorKind::Command) => {
+                match self.exchange(ErrorCode.get()).await {
+                    Ok(response) => Err(fault_error(response).unwrap_or(error)),
+                    Err(_) => Err(error),
+                }
+            }
+            result => result,
+        }
+    }
+
+    async fn exchange(&mut self, command: Command) -> Result<Response> {
+        let is_get = matches!(command, Command::Get { .. });
+        self.connection.write_command(command).await?;
+
+        check_response(is_get, self.connection.read_response().await?)
+    }
+
+    /// Queries a typed command from [`crate::commands`]
+    pub async fn get<Q: Query>(&mut self, query: Q) -> Result<Q::Output> {
+        query_output::<Q>(self.execute(query.get()).await?)
+    }
+
+    /// Sets a typed command from [`crate::commands`]
+    pub async fn set<C: Control>(&mut self, control: C, input: C::Input) -> Result<()> {
+        self.execute(control.set(input)).await?;
+        Ok(())
+    }
+}
+
+/// Appends [`DEFAULT_PORT`] to addresses without a port, such as `projector.local`,
+/// `192.168.0.1`, `fe80::1` or `[fe80::1]`
+pub fn with_default_port(addr: &str) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        addr.to_string()
+    } else if let Ok(ip) = addr.parse::<IpAddr>() {
+        SocketAddr::new(ip, DEFAULT_PORT).to_string()
+    } else if addr.ends_with(']') || !addr.contains(':') {
+        format!("{addr}:{DEFAULT_PORT}")
+    } else {
+        addr.to_string()
+    }
+}
+
+/// Resolves an address accepted by [`with_default_port`] to its first socket address
+pub fn resolve(addr: &str) -> std::io::Result<SocketAddr> {
+    std::net::ToSocketAddrs::to_socket_addrs(&with_default_port(addr))?
+        .next()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No address found for {addr}"),
+            )
+        })
+}
+
+pub(crate) fn connect_packet(password: Option<String>) -> Result<Packet> {
+    let mut packet = CONNECT_PACKET;
+    if let Some(password) = password {
+        packet
+            .headers
+            .push(Header::new(HeaderIdentifier::Password, 1, password)?);
+    }
+    Ok(packet)
+}
+
+fn timed_out() -> crate::Error {
+    crate::Error::new(
+        crate::error::ErrorKind::IO(std::io::ErrorKind::TimedOut),
+        "Timed out".to_string(),
+    )
+}
 
-        Packet::decode_from(&mut pinned_reader).await
+pub(crate) fn password_packet(
+    password: Option<String>,
+    new_password: Option<String>,
+) -> Result<Packet> {
+    let mut headers = vec![];
+    if let Some(password) = password {
+        headers.push(Header::new(HeaderIdentifier::Password, 1, password)?);
     }
+    headers.push(match new_password {
+        Some(new_password) => Header::new(HeaderIdentifier::NewPassword, 1, new_password)?,
+        None => Header::new(HeaderIdentifier::NewPassword, 0, String::new())?,
+    });
+    Ok(Packet::new_request(PacketCategory::Password, headers))
 }
 
+/// Maps the status of a `Password` reply to an error
+pub(crate) fn password_result(status: Status) -> Result<()> {
+    let message = match status {
+        Status::Ok | Status::Null => return Ok(()),
+        Status::Unauthorized => "The current password is required to change it",
+        Status::Forbidden => "The current password is wrong",
+        Status::BadRequest => "The new password was rejected",
+        Status::RequestNotAllowed => "The projector does not allow changing its password",
+        _ => return Err(status.into()),
+    };
+    Err(crate::Error::new(
+        ErrorKind::Protocol(status),
+        message.to_string(),
+    ))
+}
+
+/// Checks that a response matches the kind of command it answers
+pub(crate) fn check_response(is_get: bool, response: Response) -> Result<Response> {
+    match response {
+        Response::Error => Err(crate::Error::new(
+            ErrorKind::Command,
+            "The projector replied with an error".to_string(),
+        )),
+        Response::Value { .. } if !is_get => Err(crate::Error::new(
+            ErrorKind::Decoding,
+            format!("Unexpected reply to a set command: {response:?}"),
+        )),
+        response => Ok(response),
+    }
+}
+
+/// Error for the response to `ERR?`, if it reports a fault
+pub(crate) fn fault_error(response: Response) -> Option<crate::Error> {
+    match query_output::<ErrorCode>(response) {
+        Ok(Fault::None) | Err(_) => None,
+        Ok(fault) => Some(fault.into()),
+    }
+}
+
+pub(crate) fn query_output<Q: Query>(response: Response) -> Result<Q::Output> {
+    match response.value() {
+        Some(value) => Q::parse(value),
+        None => Err(crate::Error::new(
+            ErrorKind::Decoding,
+            format!("Missing response to {}?", Q::NAME),
+        )),
+    }
+}
+
+/// Value of the `ProjectorCommandType` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandType {
+    EscVpLevel6,
+    EscVp21,
+    Other(u8),
+}
+
+impl From<u8> for CommandType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x16 => Self::EscVpLevel6,
+            0x21 => Self::EscVp21,
+            value => Self::Other(value),
+        }
+    }
+}
+
+/// Projector described by its reply to a `Hello` packet
+#[derive(Debug, Clone, PartialEq)]
 pub struct Projector {
     addr: SocketAddr,
+    version: u8,
+    status: Status,
     name: Option<String>,
+    im_type: Option<u8>,
+    command_type: Option<CommandType>,
+    password_required: bool,
 }
 
 impl Projector {
+    pub(crate) fn from_hello(addr: SocketAddr, version: u8, packet: &Packet) -> Self {
+        let header = |identifier: HeaderIdentifier| {
+            packet
+                .headers()
+                .iter()
+                .find(move |h| *h.identifier() == identifier)
+        };
+        Self {
+            addr,
+            version,
+            status: packet.status().clone(),
+            name: header(HeaderIdentifier::ProjectorName).map(|h| h.information().to_string()),
+            im_type: header(HeaderIdentifier::ImType).map(|h| h.attribute()),
+            command_type: header(HeaderIdentifier::ProjectorCommandType)
+                .map(|h| h.attribute().into()),
+            password_required: *packet.status() == Status::Unauthorized
+                || header(HeaderIdentifier::Password).map_or(false, |h| h.attribute() != 0),
+        }
+    }
+
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
     pub fn name(&self) -> Option<String> {
         self.name.clone()
     }
+
+    /// Protocol version of the reply
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Status code of the reply
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Attribute of the `ImType` header, identifying the projector model family
+    pub fn im_type(&self) -> Option<u8> {
+        self.im_type
+    }
+
+    pub fn command_type(&self) -> Option<CommandType> {
+        self.command_type
+    }
+
+    /// Whether a password must be sent in the `Connect` handshake
+    pub fn password_required(&self) -> bool {
+        self.password_required
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::testing::{memory_server, silent_server};
+
+    #[test]
+    fn projector() {
+        let addr = "192.168.0.1:3629".parse().unwrap();
+        let packet = Packet::new(
+            PacketCategory::Hello,
+            Status::Ok,
+            vec![
+                Header::new(
+                    HeaderIdentifier::Password,
+                    1,
+                    "0000000000000000".to_string(),
+                )
+                .unwrap(),
+                Header::new(
+                    HeaderIdentifier::ProjectorName,
+                    0,
+                    "EB-L1100U0000000".to_string(),
+                )
+                .unwrap(),
+                Header::new(
+                    HeaderIdentifier::ImType,
+                    0x30,
+                    "0000000000000000".to_string(),
+                )
+                .unwrap(),
+                Header::new(
+                    HeaderIdentifier::ProjectorCommandType,
+                    0x21,
+                    "0000000000000000".to_string(),
+                )
+                .unwrap(),
+            ],
+        );
+        let projector = Projector::from_hello(addr, 0x10, &packet);
+        assert_eq!(projector.addr(), addr);
+        assert_eq!(projector.version(), 0x10);
+        assert_eq!(projector.status(), &Status::Ok);
+        assert_eq!(projector.name().as_deref(), Some("EB-L1100U0000000"));
+        assert_eq!(projector.im_type(), Some(0x30));
+        assert_eq!(projector.command_type(), Some(CommandType::EscVp21));
+        assert!(projector.password_required());
+
+        let packet = Packet::new(PacketCategory::Hello, Status::Ok, vec![]);
+        let projector = Projector::from_hello(addr, 0x10, &packet);
+        assert_eq!(projector.name(), None);
+        assert_eq!(projector.command_type(), None);
+        assert!(!projector.password_required());
+    }
+
+    #[test]
+    fn default_port() {
+        assert_eq!(with_default_port("192.168.0.1"), "192.168.0.1:3629");
+        assert_eq!(with_default_port("192.168.0.1:4352"), "192.168.0.1:4352");
+        assert_eq!(with_default_port("projector.local"), "projector.local:3629");
+        assert_eq!(
+            with_default_port("projector.local:80"),
+            "projector.local:80"
+        );
+        assert_eq!(with_default_port("fe80::1"), "[fe80::1]:3629");
+        assert_eq!(with_default_port("[fe80::1]"), "[fe80::1]:3629");
+        assert_eq!(with_default_port("[fe80::1]:80"), "[fe80::1]:80");
+    }
+
+    #[test]
+    fn resolve_addr() {
+        assert_eq!(
+            resolve("127.0.0.1").unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT))
+        );
+        assert_eq!(
+            resolve("[::1]:80").unwrap(),
+            SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 80))
+        );
+        assert!(resolve("projector:port").is_err());
+    }
+
+    #[tokio::test]
+    async fn silent_projector() {
+        let addr = silent_server().await;
+
+        let error = Client::connect(addr, None, Duration::from_millis(100))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::IO(std::io::ErrorKind::TimedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn transports() {
+        let (stream, projector) = tokio::io::duplex(64);
+        let server = memory_server(&[("PWR", "01")], None);
+        tokio::spawn(async move { server.serve_connection(projector).await });
+        let mut client = Client::handshake(stream, None).await.unwrap();
+        assert_eq!(
+            client.get(crate::commands::Power).await.unwrap(),
+            crate::commands::PowerState::On
+        );
+
+        let (stream, projector) = tokio::io::duplex(64);
+        let server = memory_server(&[("PWR", "01")], None);
+        tokio::spawn(async move { server.serve_raw(projector).await });
+        let mut client = Client::raw(stream);
+        client
+            .set(crate::commands::Power, crate::commands::Switch::Off)
+            .await
+            .unwrap();
+        let response = client.execute("PWR?".parse().unwrap()).await.unwrap();
+        assert_eq!(response.value(), Some("OFF"));
+    }
+
+    #[cfg(all(unix, feature = "serial"))]
+    #[tokio::test]
+    async fn serial() {
+        let (stream, projector) = tokio_serial::SerialStream::pair().unwrap();
+        let server = memory_server(&[("PWR", "01")], None);
+        tokio::spawn(async move { server.serve_raw(projector).await });
+        let mut client = Client::raw(stream);
+        let response = client.execute("PWR?".parse().unwrap()).await.unwrap();
+        assert_eq!(response.value(), Some("01"));
+    }
 }
@@ -1,7 +1,7 @@
 use std::{ pin::Pin, str::FromStr};
 
 use async_trait::async_trait;
-use tokio::io::{AsyncReadExt, AsyncBufReadExt, BufReader, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 
 use crate::{
     error::ErrorKind,
@@ -10,47 +10,82 @@ use crate::{
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     Get { name: String },
-    Set { name: String, value: String },
+    /// Values containing spaces are sent as quoted strings
+    Set { name: String, values: Vec<String> },
+}
+
+impl Command {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Get { name } | Self::Set { name, .. } => name,
+        }
+    }
+
+    /// Whether a command name can be sent, names being ASCII letters and digits
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_alphanumeric())
+    }
+}
+
+fn decoding_error(message: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Decoding,
+        format!("Failed to decode command: {message}"),
+    )
 }
 
 impl FromStr for Command {
     type Err = crate::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.trim_end();
-        if s.contains("?") {
-            let mut parts = s.split('?');
-            let name = parts.next().ok_or(crate::Error::new(
-                ErrorKind::Decoding,
-                "Failed to decode command".to_string(),
-            ))?;
-            Ok(Self::Get {
-                name: name.to_string(),
-            })
-        } else {
-            let mut parts = s.split(' ');
-            let name = parts.next().ok_or(crate::Error::new(
-                ErrorKind::Decoding,
-                "Failed to decode command".to_string(),
-            ))?;
-            let value = parts.next().ok_or(crate::Error::new(
-                ErrorKind::Decoding,
-                "Failed to decode command".to_string(),
-            ))?;
-            Ok(Self::Set {
-                name: name.to_string(),
-                value: value.to_string(),
-            })
+        let s = s.trim_end_matches(['\r', '\n']);
+        let end = s.find(['?', ' ']).unwrap_or(s.len());
+        let (name, rest) = s.split_at(end);
+        if !Self::is_valid_name(name) {
+            return Err(decoding_error("invalid name"));
+        }
+        let name = name.to_string();
+        if let Some(rest) = rest.strip_prefix('?') {
+            if !rest.is_empty() {
+                return Err(decoding_error("unexpected characters after '?'"));
+            }
+            return Ok(Self::Get { name });
+        }
+
+        let mut values = Vec::new();
+        let mut rest = rest.trim_start_matches(' ');
+        while !rest.is_empty() {
+            let value;
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| decoding_error("unterminated quoted value"))?;
+                (value, rest) = quoted.split_at(end);
+                rest = &rest[1..];
+                if !rest.is_empty() && !rest.starts_with(' ') {
+                    return Err(decoding_error("missing space after quoted value"));
+                }
+            } else {
+                (value, rest) = rest.split_at(rest.find(' ').unwrap_or(rest.len()));
+                if value.contains('"') {
+                    return Err(decoding_error("unexpected quote in value"));
+                }
+            }
+            values.push(value.to_string());
+            rest = rest.trim_start_matches(' ');
+        }
+        if values.is_empty() {
+            return Err(decoding_error("missing value"));
         }
+        Ok(Self::Set { name, values })
     }
 }
 #[async_trait]
-impl<R: AsyncReadExt+Send> DecodeFrom<R> for Command {
+impl<R: AsyncBufReadExt+Send> DecodeFrom<R> for Command {
     type Error = crate::Error;
     async fn decode_from(reader: &mut Pin<&mut R>) -> Result<Self, Self::Error> {
-        let mut reader = BufReader::new(reader);
-        let mut buf = String::new();
-        reader.read_line(&mut buf).await?;
-        buf.parse()
+        let mut buf = Vec::new();
+        reader.read_until(b'\r', &mut buf).await?;
+        String::from_utf8(buf)?.parse()
 
     }
 }
@@ -58,12 +93,33 @@ impl<R: AsyncReadExt+Send> DecodeFrom<R> for Command {
 impl<W: AsyncWriteExt+Send> EncodeTo<W> for Command {
     type Error = crate::Error;
     async fn encode_to(self, writer: &mut Pin<&mut W>) -> Result<usize, Self::Error> {
+        // Other characters could end the command early, and start another one
+        if !Self::is_valid_name(self.name()) {
+            return Err(crate::Error::new(
+                ErrorKind::Encoding,
+                format!("Name cannot be encoded: {:?}", self.name()),
+            ));
+        }
         let command = match self {
             Self::Get { name } => {
-                format!("{name}?\n")
+                format!("{name}?\r")
             }
-            Self::Set { name, value } => {
-                format!("{name} {value}\n")
+            Self::Set { name, values } => {
+                let mut command = name;
+                for value in values {
+                    if value.contains(['"', '\r', '\n']) {
+                        return Err(crate::Error::new(
+                            ErrorKind::Encoding,
+                            format!("Value cannot be encoded: {value:?}"),
+                        ));
+                    }
+                    if value.is_empty() || value.contains(' ') {
+                        command += &format!(" \"{value}\"");
+                    } else {
+                        command += &format!(" {value}");
+                    }
+                }
+                command + "\r"
             }
         };
         writer.write_all(command.as_bytes()).await?;
@@ -71,46 +127,95 @@ impl<W: AsyncWriteExt+Send> EncodeTo<W> for Command {
     }
 }
 
-pub struct Response {
-    name: String,
-    value: String,
+/// Reply of the projector to a command, up to its `:` prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// `NAME=value`, answering a [`Command::Get`]
+    Value { name: String, value: String },
+    /// Bare prompt, acknowledging a [`Command::Set`]
+    Acknowledged,
+    /// `ERR`, the command was rejected
+    Error,
 }
 
 impl Response {
-    pub fn name(&self) -> &str {
-        self.name.as_ref()
+    pub fn new(name: String, value: String) -> Self {
+        Self::Value { name, value }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Value { name, .. } => Some(name),
+            _ => None,
+        }
     }
 
-    pub fn value(&self) -> &str {
-        self.value.as_ref()
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Self::Value { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a reply without its prompt, `PWR=01\r`, `ERR\r` or an empty string
+impl FromStr for Response {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end_matches(['\r', '\n']);
+        match s {
+            "" => Ok(Self::Acknowledged),
+            "ERR" => Ok(Self::Error),
+            _ => {
+                let (name, value) = s.split_once('=').ok_or(crate::Error::new(
+                    ErrorKind::Decoding,
+                    format!("Failed to decode response: {s}"),
+                ))?;
+                Ok(Self::Value {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+            }
+        }
     }
 }
 #[async_trait]
-impl<R: AsyncReadExt+ Send> DecodeFrom<R> for Response {
+impl<R: AsyncBufReadExt+ Send> DecodeFrom<R> for Response {
     type Error = crate::Error;
-    async fn decode_from(reader: &mut Pin<&mut R>) -> Result<Self, Self::Error> {
-        let mut buf_reader = BufReader::new(reader);
-        let mut buf = String::new();
-        buf_reader.read_line(&mut buf).await?;
-        let mut parts = buf.split("=");
-        let name = parts
-            .next()
-            .ok_or(crate::Error::new(
-                ErrorKind::Decoding,
-                "Failed to decode response".to_string(),
-            ))?
-            .to_string();
-        let value = parts
-            .next()
-            .ok_or(crate::Error::new(
-                ErrorKind::Decoding,
-                "Failed to decode response".to_string(),
-            ))?
-            .to_string();
-
-        Ok(Self { name, value })
+    async fn decode_from(reader: &mut Pin<&mut R>) -> Result<Self, crate::Error> {
+        let mut buf = Vec::new();
+        loop {
+            if reader.read_until(b':', &mut buf).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            if let Some(prompt) = prompt_position(&buf) {
+                buf.truncate(prompt);
+                return String::from_utf8(buf)?.parse();
+            }
+        }
+    }
+}
+/// Writes the response followed by the `:` prompt
+#[async_trait]
+impl<W: AsyncWriteExt + Send> EncodeTo<W> for Response {
+    type Error = crate::Error;
+    async fn encode_to(self, writer: &mut Pin<&mut W>) -> Result<usize, crate::Error> {
+        let response = match self {
+            Self::Value { name, value } => format!("{name}={value}\r:"),
+            Self::Acknowledged => ":".to_string(),
+            Self::Error => "ERR\r:".to_string(),
+        };
+        writer.write_all(response.as_bytes()).await?;
+        Ok(response.len())
     }
 }
+
+/// Position of the `:` prompt ending `reply`, if it is at the start of a line
+pub(crate) fn prompt_position(reply: &[u8]) -> Option<usize> {
+    let prompt = reply.len().checked_sub(1)?;
+    (reply[prompt] == b':' && (prompt == 0 || matches!(reply[prompt - 1], b'\r' | b'\n')))
+        .then_some(prompt)
+}
 #[cfg(test)]
 mod tests {
 use super::*;
@@ -122,9 +227,93 @@ use super::*;
         assert_eq!(decoded_command, command);
 
         let data = "PWR ON\n";
-        let command = Command::Set { name: "PWR".to_string(), value: "ON".to_string() } ;
+        let command = Command::Set { name: "PWR".to_string(), values: vec!["ON".to_string()] } ;
         let decoded_command:Command = data.parse().unwrap();
         assert_eq!(decoded_command, command)
 
     }
+
+    #[test]
+    fn command_values() {
+        let command: Command = "LENS 12 -3\r".parse().unwrap();
+        assert_eq!(
+            command,
+            Command::Set {
+                name: "LENS".to_string(),
+                values: vec!["12".to_string(), "-3".to_string()]
+            }
+        );
+        let command: Command = "NWPNAME \"Room 101\" \"\"".parse().unwrap();
+        assert_eq!(
+            command,
+            Command::Set {
+                name: "NWPNAME".to_string(),
+                values: vec!["Room 101".to_string(), "".to_string()]
+            }
+        );
+        for data in ["PWR", "PWR? ON", "?", "NAME \"Room", "NAME \"Room\"101", "NAME Ro\"om"] {
+            assert!(data.parse::<Command>().is_err(), "{data}");
+        }
+    }
+
+    #[tokio::test]
+    async fn command_names() {
+        let commands = [
+            Command::Get { name: "PWR?\rSOURCE".to_string() },
+            Command::Get { name: String::new() },
+            Command::Set { name: "PWR ON\rPWR".to_string(), values: vec!["OFF".to_string()] },
+        ];
+        for command in commands {
+            let error = command.encode_to(&mut Pin::new(&mut Vec::new())).await.unwrap_err();
+            assert!(matches!(error.kind(), ErrorKind::Encoding));
+        }
+        assert!("PWR\rX?".parse::<Command>().is_err());
+    }
+
+    #[tokio::test]
+    async fn command_round_trip() {
+        for data in ["PWR?\r", "KEY 3C\r", "LENS 12 -3\r", "NWPNAME \"Room 101\" \"\"\r"] {
+            let command: Command = data.parse().unwrap();
+            let mut buf = Vec::new();
+            command.encode_to(&mut Pin::new(&mut buf)).await.unwrap();
+            assert_eq!(buf, data.as_bytes());
+        }
+        let command = Command::Set {
+            name: "NWPNAME".to_string(),
+            values: vec!["\"".to_string()],
+        };
+        assert!(command.encode_to(&mut Pin::new(&mut Vec::new())).await.is_err());
+    }
+
+    #[test]
+    fn response() {
+        let response: Response = "PWR=01\r".parse().unwrap();
+        assert_eq!(response.name(), Some("PWR"));
+        assert_eq!(response.value(), Some("01"));
+        let response: Response = "NWPNAME=a=b\r".parse().unwrap();
+        assert_eq!(response.value(), Some("a=b"));
+        assert_eq!("ERR\r".parse::<Response>().unwrap(), Response::Error);
+        assert_eq!("".parse::<Response>().unwrap(), Response::Acknowledged);
+        assert!("PWR".parse::<Response>().is_err());
+    }
+
+    #[tokio::test]
+    async fn response_prompt() {
+        let input = b"PWR=01\r:ERR\r::SNO=AB:CD\r:";
+        let mut data = &input[..];
+        let mut reader = Pin::new(&mut data);
+        let responses = [
+            Response::new("PWR".to_string(), "01".to_string()),
+            Response::Error,
+            Response::Acknowledged,
+            Response::new("SNO".to_string(), "AB:CD".to_string()),
+        ];
+        let mut buf = Vec::new();
+        for response in responses {
+            assert_eq!(Response::decode_from(&mut reader).await.unwrap(), response.clone());
+            response.encode_to(&mut Pin::new(&mut buf)).await.unwrap();
+        }
+        assert!(Response::decode_from(&mut reader).await.is_err());
+        assert_eq!(buf, input);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,558 @@
+//! Typed catalog of common ESC/VP21 commands
+//!
+//! Each command is a unit struct implementing [`Query`], and [`Control`] when it can be set.
+//! ```no_run
+//! # async fn example(client: &mut escvpnet::client::Client) -> escvpnet::Result<()> {
+//! use escvpnet::commands::{Lamp, Power, Query, Switch};
+//!
+//! let hours = client.get(Lamp).await?;
+//! client.set(Power, Switch::On).await?;
+//! let command = Lamp.get(); // plain `Command::Get { name: "LAMP" }`
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt, str::FromStr};
+
+use crate::{command::Command, error::ErrorKind, Result};
+
+/// A command which can be queried with `NAME?`
+pub trait Query: Sized {
+    const NAME: &'static str;
+    type Output;
+
+    /// Parses the value of the projector's response
+    fn parse(value: &str) -> Result<Self::Output>;
+
+    fn get(&self) -> Command {
+        Command::Get {
+            name: Self::NAME.to_string(),
+        }
+    }
+}
+
+/// A command which can be set with `NAME value`
+pub trait Control: Query {
+    type Input;
+
+    /// Encodes the value sent to the projector
+    fn encode(input: &Self::Input) -> String;
+
+    fn set(&self, input: Self::Input) -> Command {
+        Command::Set {
+            name: Self::NAME.to_string(),
+            values: vec![Self::encode(&input)],
+        }
+    }
+}
+
+fn decoding_error(name: &str, value: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Decoding,
+        format!("Failed to decode {name} value: {value}"),
+    )
+}
+
+fn parse_code(name: &str, value: &str) -> Result<u8> {
+    u8::from_str_radix(value.trim(), 16).map_err(|_| decoding_error(name, value))
+}
+
+/// Generates an enum of two digit hexadecimal codes, with a fallback for unknown codes
+macro_rules! code_enum {
+    ($(#[$meta: meta])* $name: ident { $($(#[$variant_meta: meta])* $variant: ident = $code: literal),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)+
+            Other(u8),
+        }
+
+        impl $name {
+            /// Known variants, without `Other`
+            pub const VARIANTS: &'static [Self] = &[$(Self::$variant),+];
+
+            pub fn code(&self) -> u8 {
+                match self {
+                    $(Self::$variant => $code,)+
+                    Self::Other(code) => *code,
+                }
+            }
+
+            pub fn from_code(code: u8) -> Self {
+                match code {
+                    $($code => Self::$variant,)+
+                    code => Self::Other(code),
+                }
+            }
+        }
+
+        /// Variant name, or hexadecimal code for unknown codes
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(Self::$variant => write!(f, stringify!($variant)),)+
+                    Self::Other(code) => write!(f, "{code:02X}"),
+                }
+            }
+        }
+
+        /// Parses a case insensitive variant name, or a hexadecimal code
+        impl FromStr for $name {
+            type Err = crate::Error;
+            fn from_str(s: &str) -> Result<Self> {
+                $(if s.eq_ignore_ascii_case(stringify!($variant)) {
+                    return Ok(Self::$variant);
+                })+
+                parse_code(stringify!($name), s).map(Self::from_code)
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+impl Switch {
+    fn parse(name: &str, value: &str) -> Result<Self> {
+        match value.trim() {
+            "ON" | "01" => Ok(Self::On),
+            "OFF" | "00" => Ok(Self::Off),
+            _ => Err(decoding_error(name, value)),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            Self::On => "ON",
+            Self::Off => "OFF",
+        }
+        .to_string()
+    }
+}
+
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+impl FromStr for Switch {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse("switch", &s.to_ascii_uppercase())
+    }
+}
+
+impl From<bool> for Switch {
+    fn from(value: bool) -> Self {
+        if value {
+            Self::On
+        } else {
+            Self::Off
+        }
+    }
+}
+
+code_enum!(
+    /// Power state returned by `PWR?`
+    PowerState {
+        /// Standby with network off
+        Standby = 0x00,
+        On = 0x01,
+        Warmup = 0x02,
+        Cooldown = 0x03,
+        /// Standby with network on
+        NetworkStandby = 0x04,
+        AbnormalStandby = 0x05,
+        AvStandby = 0x09,
+    }
+);
+
+code_enum!(
+    /// Input source used by `SOURCE`
+    Source {
+        Computer1 = 0x10,
+        Computer2 = 0x20,
+        Hdmi1 = 0x30,
+        Video = 0x41,
+        SVideo = 0x42,
+        UsbDisplay = 0x52,
+        Lan = 0x53,
+        HdBaseT = 0x80,
+        Hdmi2 = 0xA0,
+    }
+);
+
+code_enum!(
+    /// Aspect ratio used by `ASPECT`
+    Aspect {
+        Normal = 0x00,
+        Ratio4x3 = 0x10,
+        Ratio16x9 = 0x20,
+        Auto = 0x30,
+        Full = 0x40,
+        Zoom = 0x50,
+        Native = 0x60,
+    }
+);
+
+code_enum!(
+    /// Color mode used by `CMODE`
+    ColorMode {
+        Srgb = 0x01,
+        Normal = 0x02,
+        Meeting = 0x03,
+        Presentation = 0x04,
+        Theatre = 0x05,
+        Dynamic = 0x06,
+        Natural = 0x07,
+        Sports = 0x08,
+        Blackboard = 0x0A,
+        Whiteboard = 0x0B,
+    }
+);
+
+code_enum!(
+    /// Fault returned by `ERR?`
+    Fault {
+        /// No error, or error recovered
+        None = 0x00,
+        Fan = 0x01,
+        LampAtPowerOn = 0x03,
+        HighTemperature = 0x04,
+        Lamp = 0x06,
+        LampCoverOpen = 0x07,
+        CinemaFilter = 0x08,
+        CapacitorDisconnected = 0x09,
+        AutoIris = 0x0A,
+        Subsystem = 0x0B,
+        LowAirFlow = 0x0C,
+        AirFilterSensor = 0x0D,
+        PowerSupply = 0x0E,
+        Shutter = 0x0F,
+        PeltierCooling = 0x10,
+        PumpCooling = 0x11,
+        StaticIris = 0x12,
+        BallastMismatch = 0x13,
+        ExhaustShutter = 0x14,
+        ObstacleDetection = 0x15,
+        BoardDiscernment = 0x16,
+        StackCommunication = 0x17,
+        I2c = 0x18,
+    }
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    None,
+    /// The projector keeps working but needs maintenance
+    Warning,
+    /// The projector stopped, or will stop, projecting
+    Critical,
+}
+
+impl Fault {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::None => "No error",
+            Self::Fan => "Fan error",
+            Self::LampAtPowerOn => "Lamp failure at power on",
+            Self::HighTemperature => "High internal temperature",
+            Self::Lamp => "Lamp error",
+            Self::LampCoverOpen => "Lamp cover door open",
+            Self::CinemaFilter => "Cinema filter error",
+            Self::CapacitorDisconnected => "Electric dual-layered capacitor disconnected",
+            Self::AutoIris => "Auto iris error",
+            Self::Subsystem => "Subsystem error",
+            Self::LowAirFlow => "Low air flow",
+            Self::AirFilterSensor => "Air filter air flow sensor error",
+            Self::PowerSupply => "Power supply unit error",
+            Self::Shutter => "Shutter error",
+            Self::PeltierCooling => "Cooling system error (peltier element)",
+            Self::PumpCooling => "Cooling system error (pump)",
+            Self::StaticIris => "Static iris error",
+            Self::BallastMismatch => "Power supply unit error (ballast mismatch)",
+            Self::ExhaustShutter => "Exhaust shutter error",
+            Self::ObstacleDetection => "Obstacle detected",
+            Self::BoardDiscernment => "Interface board discernment error",
+            Self::StackCommunication => "Stack projection communication error",
+            Self::I2c => "I2C error",
+            Self::Other(_) => "Unknown error",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::None => Severity::None,
+            Self::CinemaFilter
+            | Self::LowAirFlow
+            | Self::AirFilterSensor
+            | Self::ObstacleDetection
+            | Self::StackCommunication => Severity::Warning,
+            _ => Severity::Critical,
+        }
+    }
+}
+
+/// `PWR`, set with [`Switch`], queried as a [`PowerState`]
+#[derive(Debug, Clone, Copy)]
+pub struct Power;
+
+impl Query for Power {
+    const NAME: &'static str = "PWR";
+    type Output = PowerState;
+    fn parse(value: &str) -> Result<PowerState> {
+        parse_code(Self::NAME, value).map(PowerState::from_code)
+    }
+}
+
+impl Control for Power {
+    type Input = Switch;
+    fn encode(input: &Switch) -> String {
+        input.encode()
+    }
+}
+
+/// `SOURCE`
+#[derive(Debug, Clone, Copy)]
+pub struct SelectSource;
+
+impl Query for SelectSource {
+    const NAME: &'static str = "SOURCE";
+    type Output = Source;
+    fn parse(value: &str) -> Result<Source> {
+        parse_code(Self::NAME, value).map(Source::from_code)
+    }
+}
+
+impl Control for SelectSource {
+    type Input = Source;
+    fn encode(input: &Source) -> String {
+        format!("{:02X}", input.code())
+    }
+}
+
+/// `LAMP?`, lamp hours
+#[derive(Debug, Clone, Copy)]
+pub struct Lamp;
+
+impl Query for Lamp {
+    const NAME: &'static str = "LAMP";
+    type Output = u32;
+    fn parse(value: &str) -> Result<u32> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| decoding_error(Self::NAME, value))
+    }
+}
+
+/// `FILTER?`, air filter hours, on models reporting them
+#[derive(Debug, Clone, Copy)]
+pub struct FilterHours;
+
+impl Query for FilterHours {
+    const NAME: &'static str = "FILTER";
+    type Output = u32;
+    fn parse(value: &str) -> Result<u32> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| decoding_error(Self::NAME, value))
+    }
+}
+
+/// `MUTE`, A/V mute
+#[derive(Debug, Clone, Copy)]
+pub struct Mute;
+
+impl Query for Mute {
+    const NAME: &'static str = "MUTE";
+    type Output = Switch;
+    fn parse(value: &str) -> Result<Switch> {
+        Switch::parse(Self::NAME, value)
+    }
+}
+
+impl Control for Mute {
+    type Input = Switch;
+    fn encode(input: &Switch) -> String {
+        input.encode()
+    }
+}
+
+/// `VOL`, volume as the projector's raw 0-255 value
+#[derive(Debug, Clone, Copy)]
+pub struct Volume;
+
+impl Query for Volume {
+    const NAME: &'static str = "VOL";
+    type Output = u8;
+    fn parse(value: &str) -> Result<u8> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| decoding_error(Self::NAME, value))
+    }
+}
+
+impl Control for Volume {
+    type Input = u8;
+    fn encode(input: &u8) -> String {
+        input.to_string()
+    }
+}
+
+/// `FREEZE`
+#[derive(Debug, Clone, Copy)]
+pub struct Freeze;
+
+impl Query for Freeze {
+    const NAME: &'static str = "FREEZE";
+    type Output = Switch;
+    fn parse(value: &str) -> Result<Switch> {
+        Switch::parse(Self::NAME, value)
+    }
+}
+
+impl Control for Freeze {
+    type Input = Switch;
+    fn encode(input: &Switch) -> String {
+        input.encode()
+    }
+}
+
+/// `ASPECT`
+#[derive(Debug, Clone, Copy)]
+pub struct SelectAspect;
+
+impl Query for SelectAspect {
+    const NAME: &'static str = "ASPECT";
+    type Output = Aspect;
+    fn parse(value: &str) -> Result<Aspect> {
+        parse_code(Self::NAME, value).map(Aspect::from_code)
+    }
+}
+
+impl Control for SelectAspect {
+    type Input = Aspect;
+    fn encode(input: &Aspect) -> String {
+        format!("{:02X}", input.code())
+    }
+}
+
+/// `CMODE`
+#[derive(Debug, Clone, Copy)]
+pub struct SelectColorMode;
+
+impl Query for SelectColorMode {
+    const NAME: &'static str = "CMODE";
+    type Output = ColorMode;
+    fn parse(value: &str) -> Result<ColorMode> {
+        parse_code(Self::NAME, value).map(ColorMode::from_code)
+    }
+}
+
+impl Control for SelectColorMode {
+    type Input = ColorMode;
+    fn encode(input: &ColorMode) -> String {
+        format!("{:02X}", input.code())
+    }
+}
+
+/// `ERR?`, fault reported by the projector
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode;
+
+impl Query for ErrorCode {
+    const NAME: &'static str = "ERR";
+    type Output = Fault;
+    fn parse(value: &str) -> Result<Fault> {
+        parse_code(Self::NAME, value).map(Fault::from_code)
+    }
+}
+
+/// `SNO?`, serial number
+#[derive(Debug, Clone, Copy)]
+pub struct SerialNumber;
+
+impl Query for SerialNumber {
+    const NAME: &'static str = "SNO";
+    type Output = String;
+    fn parse(value: &str) -> Result<String> {
+        Ok(value.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            Lamp.get(),
+            Command::Get {
+                name: "LAMP".to_string()
+            }
+        );
+        assert_eq!(Lamp::parse("1234").unwrap(), 1234);
+        assert_eq!(Power::parse("02").unwrap(), PowerState::Warmup);
+        assert_eq!(SelectSource::parse("A0").unwrap(), Source::Hdmi2);
+        assert_eq!(SelectSource::parse("B4").unwrap(), Source::Other(0xB4));
+        assert!(Power::parse("ON").is_err());
+    }
+
+    #[test]
+    fn fault() {
+        assert_eq!(ErrorCode::parse("00").unwrap(), Fault::None);
+        let fault = ErrorCode::parse("04").unwrap();
+        assert_eq!(fault, Fault::HighTemperature);
+        assert_eq!(fault.severity(), Severity::Critical);
+        assert_eq!(fault.description(), "High internal temperature");
+        assert_eq!(
+            ErrorCode::parse("0C").unwrap().severity(),
+            Severity::Warning
+        );
+        assert_eq!(ErrorCode::parse("7F").unwrap(), Fault::Other(0x7F));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("hdmi1".parse::<Source>().unwrap(), Source::Hdmi1);
+        assert_eq!("A0".parse::<Source>().unwrap(), Source::Hdmi2);
+        assert_eq!("on".parse::<Switch>().unwrap(), Switch::On);
+        assert_eq!(Source::Other(0xB4).to_string(), "B4");
+        assert_eq!(PowerState::Warmup.to_string(), "Warmup");
+        assert!("nothing".parse::<Source>().is_err());
+        assert_eq!(Source::VARIANTS.len(), 9);
+        assert!(!Source::VARIANTS.contains(&Source::Other(0x10)));
+    }
+
+    #[test]
+    fn control() {
+        assert_eq!(
+            Power.set(Switch::On),
+            Command::Set {
+                name: "PWR".to_string(),
+                values: vec!["ON".to_string()]
+            }
+        );
+        assert_eq!(
+            SelectSource.set(Source::Hdmi1),
+            Command::Set {
+                name: "SOURCE".to_string(),
+                values: vec!["30".to_string()]
+            }
+        );
+        assert_eq!(
+            SelectColorMode.set(ColorMode::Blackboard),
+            Command::Set {
+                name: "CMODE".to_string(),
+                values: vec!["0A".to_string()]
+            }
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::pin::Pin;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream};
+
+use crate::{
+    command::{Command, Response},
+    io::{DecodeFrom, EncodeTo},
+    packet::Packet,
+    Result,
+};
+
+/// Buffered stream kept for the whole session, framing both binary [`Packet`]s
+/// and line-oriented ESC/VP21 traffic.
+///
+/// Every write is flushed, and bytes read ahead stay in the buffer for the next read.
+pub struct Connection<S> {
+    stream: BufStream<S>,
+}
+
+/// Byte stream carrying ESC/VP.net or ESC/VP21, such as a `TcpStream`, a serial port
+/// or a `tokio::io::DuplexStream`
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Transport for S {}
+
+impl<S: Transport> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    pub async fn write_packet(&mut self, packet: Packet) -> Result<()> {
+        packet.encode_to(&mut Pin::new(&mut self.stream)).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    pub async fn read_packet(&mut self) -> Result<Packet> {
+        Packet::decode_from(&mut Pin::new(&mut self.stream)).await
+    }
+
+    pub async fn write_command(&mut self, command: Command) -> Result<()> {
+        command.encode_to(&mut Pin::new(&mut self.stream)).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Reads the next command line, `None` when the peer closed the connection
+    pub async fn read_command(&mut self) -> Result<Option<Command>> {
+        if self.stream.fill_buf().await?.is_empty() {
+            return Ok(None);
+        }
+        Command::decode_from(&mut Pin::new(&mut self.stream))
+            .await
+            .map(Some)
+    }
+
+    /// Reads the response to a command, up to the `:` prompt
+    pub async fn read_response(&mut self) -> Result<Response> {
+        Response::decode_from(&mut Pin::new(&mut self.stream)).await
+    }
+
+    /// Writes the response to a command, followed by the `:` prompt
+    pub async fn write_response(&mut self, response: Response) -> Result<()> {
+        response.encode_to(&mut Pin::new(&mut self.stream)).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{PacketCategory, Status};
+
+    #[tokio::test]
+    async fn consecutive_frames() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = Connection::new(client);
+        let mut server = Connection::new(server);
+
+        let packet = Packet::new(PacketCategory::Connect, Status::Ok, vec![]);
+        server.write_packet(packet.clone()).await.unwrap();
+        let value = Response::new("PWR".to_string(), "01".to_string());
+        server.write_response(value.clone()).await.unwrap();
+        server.write_response(Response::Acknowledged).await.unwrap();
+        server.write_response(Response::Error).await.unwrap();
+
+        assert_eq!(client.read_packet().await.unwrap(), packet);
+        assert_eq!(client.read_response().await.unwrap(), value);
+        assert_eq!(
+            client.read_response().await.unwrap(),
+            Response::Acknowledged
+        );
+        assert_eq!(client.read_response().await.unwrap(), Response::Error);
+
+        let command: Command = "PWR?".parse().unwrap();
+        client.write_command(command.clone()).await.unwrap();
+        client.write_command(command.clone()).await.unwrap();
+        drop(client);
+        assert_eq!(server.read_command().await.unwrap(), Some(command.clone()));
+        assert_eq!(server.read_command().await.unwrap(), Some(command));
+        assert_eq!(server.read_command().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Protocol independent projector control
+//!
+//! [`ProjectorControl`] offers power, input and status for both the ESC/VP.net clients
+//! and, with the `pjlink` feature, the PJLink client, so that fleets mixing both
+//! protocols can be handled by the same code. Every ESC/VP.net client, [`Client`],
+//! [`ClientHandle`] and [`ReconnectingClient`], implements it through [`Executor`].
+use std::fmt;
+
+use async_trait::async_trait;
+use tokio::net::ToSocketAddrs;
+
+use crate::{
+    client::{query_output, Client},
+    command::{Command, Response},
+    commands::{
+        Control, ErrorCode, Fault, Lamp, Power, PowerState, Query, SelectSource, Severity, Source,
+        Switch,
+    },
+    connection::Transport,
+    handle::ClientHandle,
+    reconnect::ReconnectingClient,
+    Result,
+};
+
+/// Summary of a projector's state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectorStatus {
+    pub power: PowerState,
+    /// Current input, `None` when unavailable such as in standby
+    pub input: Option<String>,
+    /// Hours of the (first) lamp, `None` when unavailable
+    pub lamp_hours: Option<u32>,
+    /// Most severe of the reported faults
+    pub severity: Severity,
+    /// Descriptions of the reported faults
+    pub faults: Vec<String>,
+}
+
+#[async_trait]
+pub trait ProjectorControl: Send {
+    /// Input identifier of the protocol
+    type Input: fmt::Display + Send;
+
+    async fn power(&mut self) -> Result<PowerState>;
+
+    async fn set_power(&mut self, switch: Switch) -> Result<()>;
+
+    async fn input(&mut self) -> Result<Self::Input>;
+
+    async fn set_input(&mut self, input: Self::Input) -> Result<()>;
+
+    async fn status(&mut self) -> Result<ProjectorStatus>;
+}
+
+/// Executes ESC/VP21 commands, as the `execute` method of each ESC/VP.net client
+#[async_trait]
+pub trait Executor: Send {
+    async fn execute(&mut self, command: Command) -> Result<Response>;
+}
+
+#[async_trait]
+impl<S: Transport> Executor for Client<S> {
+    async fn execute(&mut self, command: Command) -> Result<Response> {
+        Client::execute(self, command).await
+    }
+}
+
+#[async_trait]
+impl Executor for ClientHandle {
+    async fn execute(&mut self, command: Command) -> Result<Response> {
+        ClientHandle::execute(self, command).await
+    }
+}
+
+#[async_trait]
+impl<A: ToSocketAddrs + Clone + Send + Sync> Executor for ReconnectingClient<A> {
+    async fn execute(&mut self, command: Command) -> Result<Response> {
+        ReconnectingClient::execute(self, command).await
+    }
+}
+
+async fn get<E: Executor + ?Sized, Q: Query>(executor: &mut E, query: Q) -> Result<Q::Output> {
+    query_output::<Q>(executor.execute(query.get()).await?)
+}
+
+#[async_trait]
+impl<E: Executor> ProjectorControl for E {
+    type Input = Source;
+
+    async fn power(&mut self) -> Result<PowerState> {
+        get(self, Power).await
+    }
+
+    async fn set_power(&mut self, switch: Switch) -> Result<()> {
+        self.execute(Power.set(switch)).await.map(|_| ())
+    }
+
+    async fn input(&mut self) -> Result<Source> {
+        get(self, SelectSource).await
+    }
+
+    async fn set_input(&mut self, input: Source) -> Result<()> {
+        self.execute(SelectSource.set(input)).await.map(|_| ())
+    }
+
+    /// Queries `PWR?`, `SOURCE?`, `LAMP?` and `ERR?`, source and lamp hours being
+    /// unavailable on some models and in standby
+    async fn status(&mut self) -> Result<ProjectorStatus> {
+        let power = get(self, Power).await?;
+        let input = get(self, SelectSource).await.ok();
+        let lamp_hours = get(self, Lamp).await.ok();
+        let fault = get(self, ErrorCode).await?;
+        Ok(ProjectorStatus {
+            power,
+            input: input.map(|input| input.to_string()),
+            lamp_hours,
+            severity: fault.severity(),
+            faults: match fault {
+                Fault::None => vec![],
+                fault => vec![fault.description().to_string()],
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::server::testing::spawn_server;
+
+    /// Code written once for every protocol
+    async fn summary<P: ProjectorControl>(projector: &mut P) -> Result<String> {
+        let status = projector.status().await?;
+        Ok(format!("{} {}", status.power, projector.input().await?))
+    }
+
+    #[tokio::test]
+    async fn escvpnet() {
+        let values = [("PWR", "01"), ("SOURCE", "30"), ("ERR", "07")];
+        let (addr, _) = spawn_server(&values, None).await;
+
+        let mut client = Client::connect(addr, None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.status().await.unwrap(),
+            ProjectorStatus {
+                power: PowerState::On,
+                input: Some("Hdmi1".to_string()),
+                lamp_hours: None,
+                severity: Fault::from_code(0x07).severity(),
+                faults: vec![Fault::from_code(0x07).description().to_string()],
+            }
+        );
+        client.set_input(Source::Hdmi2).await.unwrap();
+        assert_eq!(summary(&mut client).await.unwrap(), "On Hdmi2");
+    }
+}
@@ -0,0 +1,201 @@
+//! Continuous discovery of projectors
+//!
+//! [`Discovery`] re-broadcasts a `Hello` packet at a fixed interval and reports
+//! projectors appearing, and disappearing once they stop answering for longer than a TTL.
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    pin::Pin,
+    time::Duration,
+};
+
+use futures_util::Stream;
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::Instant,
+};
+
+use crate::{
+    client::{Projector, HELLO_PACKET},
+    io::DecodeFrom,
+    packet::{Packet, PacketCategory, Status},
+    Result,
+};
+
+const BUF_SIZE: usize = 1024;
+
+#[derive(Debug)]
+pub enum DiscoveryEvent {
+    /// A projector answered for the first time, or again after having disappeared
+    Appeared(Projector),
+    /// A projector did not answer during the TTL, with its last known description
+    Disappeared(Projector),
+    /// A reply could not be decoded and was skipped
+    Malformed {
+        addr: SocketAddr,
+        error: crate::Error,
+    },
+}
+
+pub struct Discovery {
+    socket: UdpSocket,
+    broadcast_addr: SocketAddr,
+    interval: Duration,
+    ttl: Duration,
+    next_broadcast: Instant,
+    projectors: HashMap<SocketAddr, (Projector, Instant)>,
+    events: VecDeque<DiscoveryEvent>,
+    buf: [u8; BUF_SIZE],
+}
+
+impl Discovery {
+    pub async fn bind<A: ToSocketAddrs>(
+        bind_addr: A,
+        broadcast_addr: SocketAddr,
+        interval: Duration,
+        ttl: Duration,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            broadcast_addr,
+            interval,
+            ttl,
+            next_broadcast: Instant::now(),
+            projectors: HashMap::new(),
+            events: VecDeque::new(),
+            buf: [0; BUF_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Projectors currently present
+    pub fn projectors(&self) -> impl Iterator<Item = &Projector> {
+        self.projectors.values().map(|(projector, _)| projector)
+    }
+
+    /// Waits for the next event, broadcasting when the interval elapses.
+    ///
+    /// This method is cancel safe.
+    pub async fn next_event(&mut self) -> Result<DiscoveryEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            match tokio::time::timeout_at(self.next_broadcast, self.socket.recv_from(&mut self.buf))
+                .await
+            {
+                Ok(result) => {
+                    let (n, addr) = result?;
+                    self.receive(addr, n).await;
+                }
+                Err(_) => self.broadcast().await?,
+            }
+        }
+    }
+
+    /// Turns this discovery into a stream of events
+    pub fn into_stream(self) -> impl Stream<Item = Result<DiscoveryEvent>> {
+        futures_util::stream::unfold(self, |mut discovery| async move {
+            let event = discovery.next_event().await;
+            Some((event, discovery))
+        })
+    }
+
+    async fn receive(&mut self, addr: SocketAddr, n: usize) {
+        let packet = match Packet::decode_from(&mut Pin::new(&mut &self.buf[..n])).await {
+            Ok(packet) => packet,
+            Err(error) => {
+                self.events
+                    .push_back(DiscoveryEvent::Malformed { addr, error });
+                return;
+            }
+        };
+        // Requests, including our own broadcast, are not replies
+        if packet.category != PacketCategory::Hello || packet.status == Status::Null {
+            return;
+        }
+        let projector = Projector::from_hello(addr, self.buf[10], &packet);
+        if self
+            .projectors
+            .insert(addr, (projector.clone(), Instant::now()))
+            .is_none()
+        {
+            self.events.push_back(DiscoveryEvent::Appeared(projector));
+        }
+    }
+
+    async fn broadcast(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let ttl = self.ttl;
+        let expired: Vec<SocketAddr> = self
+            .projectors
+            .iter()
+            .filter(|(_, (_, last_seen))| now.duration_since(*last_seen) > ttl)
+            .map(|(addr, _)| *addr)
+            .collect();
+        for addr in expired {
+            if let Some((projector, _)) = self.projectors.remove(&addr) {
+                self.events
+                    .push_back(DiscoveryEvent::Disappeared(projector));
+            }
+        }
+        self.next_broadcast = now + self.interval;
+        self.socket
+            .send_to(&HELLO_PACKET, self.broadcast_addr)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{MemoryHandler, Server};
+
+    #[tokio::test]
+    async fn events() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let server = Server::new(MemoryHandler::default(), None, None);
+        let task = tokio::spawn(async move { server.serve_discovery(socket).await });
+
+        let mut discovery = Discovery::bind(
+            "127.0.0.1:0",
+            server_addr,
+            Duration::from_millis(20),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+
+        match discovery.next_event().await.unwrap() {
+            DiscoveryEvent::Appeared(projector) => assert_eq!(projector.addr(), server_addr),
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert_eq!(discovery.projectors().count(), 1);
+
+        let garbage = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        garbage
+            .send_to(b"garbage", discovery.local_addr().unwrap())
+            .await
+            .unwrap();
+        match discovery.next_event().await.unwrap() {
+            DiscoveryEvent::Malformed { addr, .. } => {
+                assert_eq!(addr, garbage.local_addr().unwrap())
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        task.abort();
+        match discovery.next_event().await.unwrap() {
+            DiscoveryEvent::Disappeared(projector) => assert_eq!(projector.addr(), server_addr),
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert_eq!(discovery.projectors().count(), 0);
+    }
+}
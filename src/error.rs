@@ -1,6 +1,9 @@
 use std::{num::TryFromIntError, string::FromUtf8Error};
 
-use crate::packet::Status;
+use crate::{
+    commands::{Fault, PowerState},
+    packet::Status,
+};
 
 #[derive(Debug)]
 
@@ -28,16 +31,22 @@ pub enum ErrorKind {
     Encoding,
     IO(std::io::ErrorKind),
     Protocol(Status),
+    Command,
+    Power(PowerState),
+    Fault(Fault),
 }
 
-impl ToString for ErrorKind {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use ErrorKind::*;
         match self {
-            Decoding => "Decoding Error".to_string(),
-            Encoding => "Encoding Error".to_string(),
-            IO(kind) => format!("I/O Error ({})", kind.to_string()),
-            Protocol(status) => format!("Protocol Error (status:{status:?})"),
+            Decoding => write!(f, "Decoding Error"),
+            Encoding => write!(f, "Encoding Error"),
+            IO(kind) => write!(f, "I/O Error ({kind})"),
+            Protocol(status) => write!(f, "Protocol Error (status:{status:?})"),
+            Command => write!(f, "Command Error"),
+            Power(state) => write!(f, "Power Error (state:{state})"),
+            Fault(fault) => write!(f, "Projector Fault ({fault})"),
         }
     }
 }
@@ -47,7 +56,7 @@ impl std::fmt::Display for Error {
         write!(
             f,
             "ESC/VP.net Error: {}, {}",
-            self.kind().to_string(),
+            self.kind(),
             self.message()
         )
     }
@@ -99,3 +108,12 @@ impl From<Status> for Error {
         }
     }
 }
+
+impl From<Fault> for Error {
+    fn from(value: Fault) -> Self {
+        Self {
+            kind: ErrorKind::Fault(value),
+            message: value.description().to_string(),
+        }
+    }
+}
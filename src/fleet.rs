@@ -0,0 +1,271 @@
+//! Commands across many projectors
+//!
+//! A [`Fleet`] holds projectors, from [`Client::discover`](crate::client::Client::discover)
+//! or configuration, each with tags to select groups of them. Commands run on all the
+//! selected projectors with a concurrency limit, and every projector keeps a single
+//! connection, reused by the following commands, as projectors only accept one session.
+use std::{collections::HashSet, net::SocketAddr, sync::Arc, time::Duration};
+
+use futures_util::future::join_all;
+use tokio::sync::{Mutex, Semaphore};
+
+use crate::{
+    client::{query_output, Projector},
+    command::{Command, Response},
+    commands::{Control, Query},
+    error::ErrorKind,
+    handle::ClientHandle,
+    Result,
+};
+
+struct Member {
+    addr: SocketAddr,
+    password: Option<String>,
+    tags: HashSet<String>,
+    handle: Mutex<Option<ClientHandle>>,
+}
+
+impl Member {
+    async fn execute(&self, command: Command, timeout: Duration) -> Result<Response> {
+        let mut handle = self.handle.lock().await;
+        if handle.is_none() {
+            let connected =
+                ClientHandle::connect(self.addr, self.password.clone(), timeout).await?;
+            *handle = Some(connected.with_timeout(timeout));
+        }
+        let result = handle
+            .as_ref()
+            .expect("connected above")
+            .execute(command)
+            .await;
+        // The connection task has closed the session, as it is lost or out of sync after
+        // a timeout, and the next command opens a new one
+        if matches!(&result, Err(error) if matches!(error.kind(), ErrorKind::IO(_))) {
+            *handle = None;
+        }
+        result
+    }
+}
+
+/// Result of a command for each selected projector, in the order they were added
+#[derive(Debug)]
+pub struct Report<T> {
+    results: Vec<(SocketAddr, Result<T>)>,
+}
+
+impl<T> Report<T> {
+    pub fn results(&self) -> &[(SocketAddr, Result<T>)] {
+        &self.results
+    }
+
+    pub fn into_results(self) -> Vec<(SocketAddr, Result<T>)> {
+        self.results
+    }
+
+    /// Result for a projector, `None` if it was not selected
+    pub fn get(&self, addr: SocketAddr) -> Option<&Result<T>> {
+        self.results
+            .iter()
+            .find(|(member, _)| *member == addr)
+            .map(|(_, result)| result)
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = (SocketAddr, &T)> {
+        self.results
+            .iter()
+            .filter_map(|(addr, result)| Some((*addr, result.as_ref().ok()?)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (SocketAddr, &crate::Error)> {
+        self.results
+            .iter()
+            .filter_map(|(addr, result)| Some((*addr, result.as_ref().err()?)))
+    }
+
+    /// Whether the command succeeded on every selected projector
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+pub struct Fleet {
+    members: Vec<Member>,
+    semaphore: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+impl Fleet {
+    /// Creates an empty fleet running commands on at most `concurrency` projectors at once,
+    /// `timeout` applying to each connection and command
+    pub fn new(concurrency: usize, timeout: Duration) -> Self {
+        Self {
+            members: Vec::new(),
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            timeout,
+        }
+    }
+
+    /// Adds a projector, replacing its password and tags if it is already in the fleet
+    pub fn add<T: Into<String>>(
+        &mut self,
+        addr: SocketAddr,
+        password: Option<String>,
+        tags: impl IntoIterator<Item = T>,
+    ) {
+        let member = Member {
+            addr,
+            password,
+            tags: tags.into_iter().map(Into::into).collect(),
+            handle: Mutex::new(None),
+        };
+        match self.members.iter_mut().find(|member| member.addr == addr) {
+            Some(existing) => *existing = member,
+            None => self.members.push(member),
+        }
+    }
+
+    /// Adds discovered projectors, on the ESC/VP.net port they answered from
+    pub fn add_discovered<'a, T: Into<String> + Clone + 'a>(
+        &mut self,
+        projectors: impl IntoIterator<Item = &'a Projector>,
+        password: Option<String>,
+        tags: &[T],
+    ) {
+        for projector in projectors {
+            self.add(projector.addr(), password.clone(), tags.iter().cloned());
+        }
+    }
+
+    pub fn remove(&mut self, addr: SocketAddr) {
+        self.members.retain(|member| member.addr != addr);
+    }
+
+    /// Addresses of the projectors with `tag`, or of all of them for `None`
+    pub fn addrs(&self, tag: Option<&str>) -> Vec<SocketAddr> {
+        self.select(tag).map(|member| member.addr).collect()
+    }
+
+    /// Executes a command on the projectors with `tag`, or on all of them for `None`
+    pub async fn execute(&self, tag: Option<&str>, command: Command) -> Report<Response> {
+        let timeout = self.timeout;
+        let results = join_all(self.select(tag).map(|member| {
+            let command = command.clone();
+            async move {
+                let _permit = self.semaphore.acquire().await.expect("never closed");
+                (member.addr, member.execute(command, timeout).await)
+            }
+        }))
+        .await;
+        Report { results }
+    }
+
+    /// Queries a typed command from [`crate::commands`] on the selected projectors
+    pub async fn get<Q: Query>(&self, tag: Option<&str>, query: Q) -> Report<Q::Output> {
+        let report = self.execute(tag, query.get()).await;
+        Report {
+            results: report
+                .results
+                .into_iter()
+                .map(|(addr, result)| (addr, result.and_then(query_output::<Q>)))
+                .collect(),
+        }
+    }
+
+    /// Sets a typed command from [`crate::commands`] on the selected projectors
+    pub async fn set<C: Control>(
+        &self,
+        tag: Option<&str>,
+        control: C,
+        input: C::Input,
+    ) -> Report<()> {
+        let report = self.execute(tag, control.set(input)).await;
+        Report {
+            results: report
+                .results
+                .into_iter()
+                .map(|(addr, result)| (addr, result.map(|_| ())))
+                .collect(),
+        }
+    }
+
+    fn select<'a>(&'a self, tag: Option<&'a str>) -> impl Iterator<Item = &'a Member> {
+        self.members
+            .iter()
+            .filter(move |member| tag.map_or(true, |tag| member.tags.contains(tag)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        commands::{Power, PowerState, Switch},
+        server::testing::{memory_server, silent_server, spawn_single, spawn_stalling},
+    };
+
+    #[tokio::test]
+    async fn fleet() {
+        let hall = spawn_single(memory_server(&[("PWR", "01")], None)).await;
+        let lobby = spawn_single(memory_server(&[("PWR", "04")], None)).await;
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mut fleet = Fleet::new(2, Duration::from_secs(1));
+        fleet.add(hall, None, ["building-a", "hall"]);
+        fleet.add(lobby, None, ["building-a"]);
+        fleet.add(closed, None, ["building-b"]);
+        assert_eq!(fleet.addrs(Some("building-a")), vec![hall, lobby]);
+
+        let report = fleet.get(None, Power).await;
+        assert_eq!(report.get(hall).unwrap().as_ref().unwrap(), &PowerState::On);
+        assert_eq!(
+            report.get(lobby).unwrap().as_ref().unwrap(),
+            &PowerState::NetworkStandby
+        );
+        assert!(matches!(
+            report.failed().collect::<Vec<_>>()[..],
+            [(addr, _)] if addr == closed
+        ));
+
+        // Reusing the connections, as the servers accept only one
+        let report = fleet.set(Some("building-a"), Power, Switch::Off).await;
+        assert!(report.is_success());
+        assert_eq!(report.results().len(), 2);
+        let report = fleet.execute(Some("hall"), "PWR?".parse().unwrap()).await;
+        assert_eq!(
+            report.get(hall).unwrap().as_ref().unwrap().value(),
+            Some("OFF")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_projector() {
+        let silent = silent_server().await;
+
+        let mut fleet = Fleet::new(1, Duration::from_secs(1));
+        fleet.add(silent, None, ["hall"]);
+        let report = fleet.get(None, Power).await;
+        assert!(matches!(
+            report.get(silent).unwrap().as_ref().unwrap_err().kind(),
+            ErrorKind::IO(std::io::ErrorKind::TimedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn silent_session() {
+        let hall = spawn_stalling(memory_server(&[("PWR", "01")], None)).await;
+
+        let mut fleet = Fleet::new(1, Duration::from_millis(200));
+        fleet.add(hall, None, ["hall"]);
+        let report = fleet.get(None, Power).await;
+        assert!(report.get(hall).unwrap().is_err());
+        // Only accepted once the silent session is closed
+        let report = fleet.get(None, Power).await;
+        assert_eq!(report.get(hall).unwrap().as_ref().unwrap(), &PowerState::On);
+    }
+}
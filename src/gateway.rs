@@ -0,0 +1,340 @@
+//! HTTP gateway to projectors
+//!
+//! [`router`] exposes discovery and ESC/VP21 commands as JSON over HTTP, each projector
+//! being addressed by `host` or `host:port` in the path:
+//!
+//! | Method | Path | Body |
+//! |--------|------|------|
+//! | `GET` | `/projectors` | |
+//! | `GET` | `/projectors/{addr}/commands/{name}` | |
+//! | `PUT` | `/projectors/{addr}/commands/{name}` | `{"values": ["30"]}` |
+//! | `GET`, `PUT` | `/projectors/{addr}/power` | `{"power": "on"}` |
+//! | `GET`, `PUT` | `/projectors/{addr}/source` | `{"source": "hdmi1"}` |
+//! | `GET` | `/projectors/{addr}/status` | |
+//!
+//! Connections are kept open between requests. Errors are answered with a status code
+//! from [`status_code`] and a body like `{"error": "Command Error", "message": "..."}`.
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    client::{query_output, Client},
+    command::{self, Command},
+    commands::{Control, Power, Query, SelectSource, Source, Switch},
+    control::{ProjectorControl, ProjectorStatus},
+    error::ErrorKind,
+    handle::Connections,
+    packet::Status,
+};
+
+pub struct Gateway {
+    timeout: Duration,
+    bind_addr: String,
+    broadcast_addr: String,
+    connections: Arc<Connections>,
+}
+
+impl Gateway {
+    /// Creates a gateway connecting with `password`, discovering projectors by
+    /// broadcasting from `bind_addr` to `broadcast_addr`
+    pub fn new(
+        password: Option<String>,
+        timeout: Duration,
+        bind_addr: String,
+        broadcast_addr: String,
+    ) -> Self {
+        Self {
+            timeout,
+            bind_addr,
+            broadcast_addr,
+            connections: Arc::new(Connections::new(password, timeout)),
+        }
+    }
+
+    /// Connections to the projectors, to share them such as with a `metrics::Exporter`
+    pub fn connections(&self) -> Arc<Connections> {
+        self.connections.clone()
+    }
+
+    async fn execute(&self, addr: &str, command: Command) -> crate::Result<command::Response> {
+        let result = self.connections.get(addr).await?.execute(command).await;
+        self.connections.check(addr, result)
+    }
+
+    async fn status(&self, addr: &str) -> crate::Result<ProjectorStatus> {
+        let result = self.connections.get(addr).await?.status().await;
+        self.connections.check(addr, result)
+    }
+
+    async fn get<Q: Query>(&self, addr: &str, query: Q) -> crate::Result<Q::Output> {
+        query_output::<Q>(self.execute(addr, query.get()).await?)
+    }
+
+    async fn set<C: Control>(&self, addr: &str, control: C, input: C::Input) -> crate::Result<()> {
+        self.execute(addr, control.set(input)).await?;
+        Ok(())
+    }
+}
+
+pub fn router(gateway: Gateway) -> Router {
+    Router::new()
+        .route("/projectors", get(projectors))
+        .route(
+            "/projectors/{addr}/commands/{name}",
+            get(get_command).put(set_command),
+        )
+        .route("/projectors/{addr}/power", get(get_power).put(set_power))
+        .route("/projectors/{addr}/source", get(get_source).put(set_source))
+        .route("/projectors/{addr}/status", get(status))
+        .with_state(Arc::new(gateway))
+}
+
+/// HTTP status code for an error
+pub fn status_code(kind: &ErrorKind) -> StatusCode {
+    use std::io::ErrorKind as Io;
+    match kind {
+        ErrorKind::Encoding => StatusCode::BAD_REQUEST,
+        ErrorKind::Command => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorKind::Power(_) => StatusCode::CONFLICT,
+        ErrorKind::Fault(_) => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::Protocol(Status::Unauthorized) => StatusCode::UNAUTHORIZED,
+        ErrorKind::Protocol(Status::Forbidden) => StatusCode::FORBIDDEN,
+        ErrorKind::Protocol(Status::ServiceUnavailable) => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::IO(Io::TimedOut) => StatusCode::GATEWAY_TIMEOUT,
+        ErrorKind::Decoding | ErrorKind::IO(_) | ErrorKind::Protocol(_) => StatusCode::BAD_GATEWAY,
+    }
+}
+
+struct Error(crate::Error);
+
+impl From<crate::Error> for Error {
+    fn from(error: crate::Error) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let body = json!({ "error": self.0.kind().to_string(), "message": self.0.message() });
+        (status_code(&self.0.kind()), Json(body)).into_response()
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+async fn projectors(State(gateway): State<Arc<Gateway>>) -> Result<Json<Value>> {
+    let projectors = Client::discover(
+        gateway.bind_addr.as_str(),
+        gateway.broadcast_addr.as_str(),
+        gateway.timeout,
+    )
+    .await?;
+    Ok(Json(Value::Array(
+        projectors.iter().map(crate::json::projector).collect(),
+    )))
+}
+
+async fn get_command(
+    State(gateway): State<Arc<Gateway>>,
+    Path((addr, name)): Path<(String, String)>,
+) -> Result<Json<Value>> {
+    check_name(&name)?;
+    let response = gateway.execute(&addr, Command::Get { name }).await?;
+    Ok(Json(
+        json!({ "name": response.name(), "value": response.value() }),
+    ))
+}
+
+async fn set_command(
+    State(gateway): State<Arc<Gateway>>,
+    Path((addr, name)): Path<(String, String)>,
+    Json(body): Json<Value>,
+) -> Result<StatusCode> {
+    check_name(&name)?;
+    let values = body["values"]
+        .as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .filter(|values| !values.is_empty())
+        .ok_or_else(|| body_error("\"values\" must be a non-empty array of strings"))?;
+    gateway
+        .execute(&addr, Command::Set { name, values })
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_power(
+    State(gateway): State<Arc<Gateway>>,
+    Path(addr): Path<String>,
+) -> Result<Json<Value>> {
+    let power = gateway.get(&addr, Power).await?;
+    Ok(Json(json!({ "power": power.to_string() })))
+}
+
+async fn set_power(
+    State(gateway): State<Arc<Gateway>>,
+    Path(addr): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<StatusCode> {
+    let switch: Switch = body_field(&body, "power")?;
+    gateway.set(&addr, Power, switch).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_source(
+    State(gateway): State<Arc<Gateway>>,
+    Path(addr): Path<String>,
+) -> Result<Json<Value>> {
+    let source = gateway.get(&addr, SelectSource).await?;
+    Ok(Json(json!({ "source": source.to_string() })))
+}
+
+async fn set_source(
+    State(gateway): State<Arc<Gateway>>,
+    Path(addr): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<StatusCode> {
+    let source: Source = body_field(&body, "source")?;
+    gateway.set(&addr, SelectSource, source).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn status(
+    State(gateway): State<Arc<Gateway>>,
+    Path(addr): Path<String>,
+) -> Result<Json<Value>> {
+    let status = gateway.status(&addr).await?;
+    Ok(Json(crate::json::status(&status)))
+}
+
+/// Rejects names that would not be sent as a single command, before connecting
+fn check_name(name: &str) -> Result<()> {
+    if Command::is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(body_error(&format!("Invalid command name {name:?}")))
+    }
+}
+
+fn body_field<T: std::str::FromStr>(body: &Value, field: &str) -> Result<T> {
+    body[field]
+        .as_str()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| body_error(&format!("Invalid or missing \"{field}\"")))
+}
+
+fn body_error(message: &str) -> Error {
+    Error(crate::Error::new(ErrorKind::Encoding, message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::server::testing::{silent_server, spawn_server};
+
+    async fn request(router: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn gateway() {
+        let values = [("PWR", "01"), ("SOURCE", "30"), ("ERR", "00")];
+        let (addr, server) = spawn_server(&values, None).await;
+        let socket = tokio::net::UdpSocket::bind(addr).await.unwrap();
+        tokio::spawn(async move { server.serve_discovery(socket).await });
+        let router = router(Gateway::new(
+            None,
+            Duration::from_secs(1),
+            "127.0.0.1:0".to_string(),
+            addr.to_string(),
+        ));
+        let projector = format!("/projectors/{addr}");
+
+        let (status, body) =
+            request(&router, "GET", &format!("{projector}/power"), Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "power": "On" }));
+
+        let uri = format!("{projector}/source");
+        let (status, _) = request(&router, "PUT", &uri, json!({ "source": "hdmi2" })).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, body) = request(&router, "GET", &format!("{projector}/status"), Value::Null).await;
+        assert_eq!(body["input"], "Hdmi2");
+        assert_eq!(body["faults"], json!([]));
+
+        let uri = format!("{projector}/commands/VOL");
+        let (status, body) = request(&router, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "Command Error");
+        let (status, _) = request(&router, "PUT", &uri, json!({ "values": ["12"] })).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, body) = request(&router, "GET", &uri, Value::Null).await;
+        assert_eq!(body, json!({ "name": "VOL", "value": "12" }));
+        let (status, _) = request(&router, "PUT", &uri, json!({ "values": 12 })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let uri = format!("{projector}/commands/PWR%3F%0DPWR");
+        let (status, _) = request(&router, "GET", &uri, Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = request(&router, "GET", "/projectors", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["address"], addr.to_string());
+    }
+
+    #[tokio::test]
+    async fn silent_projector() {
+        let silent = silent_server().await;
+        let (addr, _) = spawn_server(&[("PWR", "01"), ("ERR", "00")], None).await;
+        let router = router(Gateway::new(
+            None,
+            Duration::from_millis(500),
+            "127.0.0.1:0".to_string(),
+            "127.0.0.1:0".to_string(),
+        ));
+
+        let silent_request = {
+            let router = router.clone();
+            let uri = format!("/projectors/{silent}/status");
+            tokio::spawn(async move { request(&router, "GET", &uri, Value::Null).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let uri = format!("/projectors/{addr}/status");
+        let (status, body) = tokio::time::timeout(
+            Duration::from_millis(250),
+            request(&router, "GET", &uri, Value::Null),
+        )
+        .await
+        .expect("not held up by the silent projector");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["power"], "On");
+
+        let (status, _) = silent_request.await.unwrap();
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    }
+}
@@ -0,0 +1,373 @@
+//! Client shared between tasks
+//!
+//! A [`ClientHandle`] sends requests to a task owning the connection, which executes
+//! them one at a time. Handles are cheap to clone, and a request abandoned by its caller,
+//! by a timeout or by dropping the future, still completes on the connection so that
+//! the next reply is not mistaken for its own. The task closes the connection after an
+//! IO error, when the projector does not answer within the timeout given to
+//! [`ClientHandle::spawn_with_timeout`], or once every handle is dropped.
+//!
+//! [`Connections`] keeps one handle per projector address, for servers sharing each
+//! projector's single session between their requests.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex as SyncMutex},
+    time::Duration,
+};
+
+use tokio::{
+    net::ToSocketAddrs,
+    sync::{mpsc, oneshot, watch, Mutex},
+};
+
+use crate::{
+    client::{query_output, with_default_port, Client},
+    command::{Command, Response},
+    commands::{Control, Query},
+    connection::Transport,
+    error::ErrorKind,
+    packet::Packet,
+    Result,
+};
+
+const QUEUE_SIZE: usize = 32;
+
+enum Request {
+    Execute(Command, oneshot::Sender<Result<Response>>),
+    SendPacket(Packet, oneshot::Sender<Result<Packet>>),
+}
+
+#[derive(Clone)]
+pub struct ClientHandle {
+    sender: mpsc::Sender<Request>,
+    timeout: Option<Duration>,
+    /// Dropped with the last handle, stopping the task even while it waits for a reply
+    _alive: watch::Receiver<()>,
+}
+
+impl ClientHandle {
+    /// Connects and spawns the task owning the connection, `timeout` also limiting each
+    /// request on the connection
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        password: Option<String>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let client = Client::connect(addr, password, timeout).await?;
+        Ok(Self::spawn_with_timeout(client, timeout))
+    }
+
+    /// Spawns a task owning `client`, which stops once every handle is dropped
+    pub fn spawn<S: Transport + 'static>(client: Client<S>) -> Self {
+        Self::spawn_task(client, None)
+    }
+
+    /// Spawns a task owning `client`, which also stops when the projector does not answer
+    /// a request within `timeout`, the connection being out of sync
+    pub fn spawn_with_timeout<S: Transport + 'static>(
+        client: Client<S>,
+        timeout: Duration,
+    ) -> Self {
+        Self::spawn_task(client, Some(timeout))
+    }
+
+    fn spawn_task<S: Transport + 'static>(
+        mut client: Client<S>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let (sender, mut receiver) = mpsc::channel(QUEUE_SIZE);
+        let (handles, alive) = watch::channel(());
+        tokio::spawn(async move {
+            while let Some(request) = receiver.recv().await {
+                let close = match request {
+                    Request::Execute(command, reply) => {
+                        answer(reply, timeout, &handles, client.execute(command)).await
+                    }
+                    Request::SendPacket(packet, reply) => {
+                        answer(reply, timeout, &handles, client.send_packet(packet)).await
+                    }
+                };
+                if close {
+                    return;
+                }
+            }
+        });
+        Self {
+            sender,
+            timeout: None,
+            _alive: alive,
+        }
+    }
+
+    /// Returns a handle whose requests fail after `timeout`, including the time spent queued
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Executes a command, see [`Client::execute`]
+    pub async fn execute(&self, command: Command) -> Result<Response> {
+        self.request(|reply| Request::Execute(command, reply)).await
+    }
+
+    pub async fn send_packet(&self, packet: Packet) -> Result<Packet> {
+        self.request(|reply| Request::SendPacket(packet, reply))
+            .await
+    }
+
+    /// Queries a typed command from [`crate::commands`]
+    pub async fn get<Q: Query>(&self, query: Q) -> Result<Q::Output> {
+        query_output::<Q>(self.execute(query.get()).await?)
+    }
+
+    /// Sets a typed command from [`crate::commands`]
+    pub async fn set<C: Control>(&self, control: C, input: C::Input) -> Result<()> {
+        self.execute(control.set(input)).await?;
+        Ok(())
+    }
+
+    async fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T>>) -> Request,
+    ) -> Result<T> {
+        let (reply, receiver) = oneshot::channel();
+        let exchange = async {
+            self.sender
+                .send(request(reply))
+                .await
+                .map_err(|_| stopped_error())?;
+            receiver.await.map_err(|_| stopped_error())?
+        };
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, exchange)
+                .await
+                .unwrap_or_else(|_| Err(timed_out())),
+            None => exchange.await,
+        }
+    }
+}
+
+/// Handles to projectors by address, connecting on first use
+pub struct Connections {
+    password: Option<String>,
+    timeout: Duration,
+    handles: SyncMutex<HashMap<String, Arc<Mutex<Option<ClientHandle>>>>>,
+}
+
+impl Connections {
+    /// Creates connections with `password`, connecting and each request being limited to
+    /// `timeout`
+    pub fn new(password: Option<String>, timeout: Duration) -> Self {
+        Self {
+            password,
+            timeout,
+            handles: SyncMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Handle to the projector at `addr`, as `host` or `host:port`, connecting to it if
+    /// needed. Connecting only holds up the requests to the same address.
+    pub async fn get(&self, addr: &str) -> Result<ClientHandle> {
+        let addr = with_default_port(addr);
+        let slot = self
+            .handles
+            .lock()
+            .expect("no panic while locked")
+            .entry(addr.clone())
+            .or_default()
+            .clone();
+        let mut handle = slot.lock().await;
+        if let Some(handle) = handle.as_ref() {
+            return Ok(handle.clone());
+        }
+        let connected =
+            match ClientHandle::connect(addr.as_str(), self.password.clone(), self.timeout).await {
+                Ok(connected) => connected.with_timeout(self.timeout),
+                Err(error) => {
+                    // Keeps no entry for addresses that were never reached
+                    let mut handles = self.handles.lock().expect("no panic while locked");
+                    if handles
+                        .get(&addr)
+                        .map_or(false, |entry| Arc::ptr_eq(entry, &slot))
+                    {
+                        handles.remove(&addr);
+                    }
+                    return Err(error);
+                }
+            };
+        *handle = Some(connected.clone());
+        Ok(connected)
+    }
+
+    /// Drops the handle to `addr` after an IO error, as the connection is closed or out
+    /// of sync after a timeout, so that the next request reconnects
+    pub fn check<T>(&self, addr: &str, result: Result<T>) -> Result<T> {
+        if matches!(&result, Err(error) if matches!(error.kind(), ErrorKind::IO(_))) {
+            self.handles
+                .lock()
+                .expect("no panic while locked")
+                .remove(&with_default_port(addr));
+        }
+        result
+    }
+}
+
+/// Answers a request not abandoned yet, returning whether the connection must be closed:
+/// after an IO error, including a timeout, or once every handle is dropped
+async fn answer<T>(
+    reply: oneshot::Sender<Result<T>>,
+    timeout: Option<Duration>,
+    handles: &watch::Sender<()>,
+    exchange: impl Future<Output = Result<T>>,
+) -> bool {
+    if reply.is_closed() {
+        return false;
+    }
+    let exchange = async {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, exchange)
+                .await
+                .unwrap_or_else(|_| Err(timed_out())),
+            None => exchange.await,
+        }
+    };
+    let result = tokio::select! {
+        result = exchange => result,
+        _ = handles.closed() => return true,
+    };
+    let close = matches!(&result, Err(error) if matches!(error.kind(), ErrorKind::IO(_)));
+    let _ = reply.send(result);
+    close
+}
+
+fn timed_out() -> crate::Error {
+    crate::Error::new(
+        ErrorKind::IO(std::io::ErrorKind::TimedOut),
+        "Timed out waiting for the projector's reply".to_string(),
+    )
+}
+
+fn stopped_error() -> crate::Error {
+    crate::Error::new(
+        ErrorKind::IO(std::io::ErrorKind::NotConnected),
+        "The connection task has stopped".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        commands::{Power, PowerState},
+        server::{
+            testing::{memory_server, spawn, spawn_stalling},
+            Handler, Server,
+        },
+    };
+
+    /// Answers every query with its own name, slowly for `SLOW?`
+    struct Echo;
+
+    #[async_trait]
+    impl Handler for Echo {
+        async fn handle(&self, command: Command) -> Result<Option<String>> {
+            if command.name() == "SLOW" {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            match command {
+                Command::Get { name } if name == "PWR" => Ok(Some("01".to_string())),
+                Command::Get { name } => Ok(Some(name)),
+                Command::Set { .. } => Ok(None),
+            }
+        }
+    }
+
+    async fn spawn_handle() -> ClientHandle {
+        let addr = spawn(Server::new(Echo, None, None)).await;
+        ClientHandle::connect(addr, None, Duration::from_secs(1))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests() {
+        let handle = spawn_handle().await;
+        let tasks: Vec<_> = (0..10)
+            .map(|i| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    let name = format!("Q{i}");
+                    let response = handle.execute(Command::Get { name: name.clone() }).await;
+                    assert_eq!(response.unwrap().value(), Some(name.as_str()));
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(handle.get(Power).await.unwrap(), PowerState::On);
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let handle = spawn_handle().await;
+        let error = handle
+            .clone()
+            .with_timeout(Duration::from_millis(50))
+            .execute("SLOW?".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::IO(std::io::ErrorKind::TimedOut)
+        ));
+        // The reply to the abandoned request is not taken for this one's
+        let response = handle.execute("NEXT?".parse().unwrap()).await.unwrap();
+        assert_eq!(response.value(), Some("NEXT"));
+    }
+    #[tokio::test]
+    async fn silent_session() {
+        let addr = spawn_stalling(memory_server(&[("PWR", "01")], None)).await;
+        let connections = Connections::new(None, Duration::from_millis(200));
+
+        let handle = connections.get(&addr.to_string()).await.unwrap();
+        let error = connections
+            .check(&addr.to_string(), handle.get(Power).await)
+            .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::IO(std::io::ErrorKind::TimedOut)
+        ));
+        // The silent session is closed, so that the projector accepts a new one
+        let handle = connections.get(&addr.to_string()).await.unwrap();
+        assert_eq!(handle.get(Power).await.unwrap(), PowerState::On);
+    }
+    #[tokio::test]
+    async fn dropped_handles() {
+        let addr = spawn_stalling(memory_server(&[("PWR", "01")], None)).await;
+        let timeout = Duration::from_millis(200);
+
+        let client = Client::connect(addr, None, timeout).await.unwrap();
+        let handle = ClientHandle::spawn(client).with_timeout(timeout);
+        assert!(handle.get(Power).await.is_err());
+        // The task waiting for the reply stops and closes the session
+        drop(handle);
+        let handle = ClientHandle::connect(addr, None, timeout).await.unwrap();
+        assert_eq!(handle.get(Power).await.unwrap(), PowerState::On);
+    }
+    #[tokio::test]
+    async fn failed_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let connections = Connections::new(None, Duration::from_millis(200));
+        assert!(connections.get(&addr.to_string()).await.is_err());
+        assert!(connections.handles.lock().unwrap().is_empty());
+    }
+}
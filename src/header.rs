@@ -1,6 +1,4 @@
-use std::{
-    pin::Pin,
-};
+use std::pin::Pin;
 
 use async_trait::async_trait;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -14,13 +12,24 @@ pub struct Header {
 }
 
 impl Header {
+    /// Size of the information field, shorter values being padded with NUL bytes
+    pub const INFORMATION_LENGTH: usize = 16;
+
+    /// Creates a header, `information` being ASCII without NUL bytes and at most
+    /// [`Self::INFORMATION_LENGTH`] bytes long
     pub fn new(identifier: HeaderIdentifier, attribute: u8, information: String) -> Result<Self> {
-        if information.len() > 16 {
+        if information.len() > Self::INFORMATION_LENGTH {
             return Err(crate::Error::new(
                 ErrorKind::Encoding,
                 "Header length is too big".to_string(),
             ));
         }
+        if !information.is_ascii() || information.contains('\0') {
+            return Err(crate::Error::new(
+                ErrorKind::Encoding,
+                format!("Header information must be ASCII without NUL bytes: {information:?}"),
+            ));
+        }
         Ok(Self {
             identifier,
             attribute,
@@ -43,14 +52,20 @@ impl Header {
 
 impl Length for Header {
     const LENGTH: usize = 18;
+    type Bytes = [u8; 18];
 }
 
 impl Decode for Header {
     type Error = crate::Error;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self> {
+    fn decode(data: Self::Bytes) -> Result<Self> {
         let identifier = HeaderIdentifier::decode(data[0..1].try_into().unwrap())?;
         let attribute = data[1];
-        let information = String::from_utf8(data[2..].to_vec())?;
+        let information = &data[2..];
+        let end = information
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(information.len());
+        let information = String::from_utf8(information[..end].to_vec())?;
         Ok(Self {
             identifier,
             attribute,
@@ -87,11 +102,12 @@ impl<W: AsyncWriteExt + Send> EncodeTo<W> for Vec<Header> {
 
 impl Encode for Header {
     type Error = crate::Error;
-    fn encode(self) -> Result<[u8; Self::LENGTH]> {
+    fn encode(self) -> Result<Self::Bytes> {
         let mut data = [0; Self::LENGTH];
         data[0] = self.identifier.encode()?[0];
         data[1] = self.attribute;
-        data[2..Self::LENGTH].copy_from_slice(self.information.as_bytes());
+        let information = self.information.as_bytes();
+        data[2..2 + information.len()].copy_from_slice(information);
         Ok(data)
     }
 }
@@ -107,11 +123,12 @@ pub enum HeaderIdentifier {
 
 impl Length for HeaderIdentifier {
     const LENGTH: usize = 1;
+    type Bytes = [u8; 1];
 }
 
 impl Decode for HeaderIdentifier {
     type Error = crate::Error;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self> {
+    fn decode(data: Self::Bytes) -> Result<Self> {
         use HeaderIdentifier::*;
         match data[0] {
             0 => Ok(Null), // Reserved,
@@ -130,7 +147,7 @@ impl Decode for HeaderIdentifier {
 
 impl Encode for HeaderIdentifier {
     type Error = crate::Error;
-    fn encode(self) -> Result<[u8; Self::LENGTH]> {
+    fn encode(self) -> Result<Self::Bytes> {
         Ok([self as u8])
     }
 }
@@ -141,7 +158,7 @@ mod tests {
 
     #[test]
     fn header() {
-        let data = *b"\0\00123456789abcdef";
+        let data = *b"\0\x000123456789abcdef";
         let header = Header {
             identifier: HeaderIdentifier::Null,
             attribute: 0,
@@ -150,6 +167,43 @@ mod tests {
         assert_eq!(header, Header::decode(data).unwrap())
     }
 
+    #[test]
+    fn information_padding() {
+        let header = Header::new(HeaderIdentifier::Password, 1, "secret".to_string()).unwrap();
+        let data = header.clone().encode().unwrap();
+        assert_eq!(data, *b"\x01\x01secret\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(Header::decode(data).unwrap(), header);
+
+        let header = Header::new(
+            HeaderIdentifier::Password,
+            1,
+            "0123456789abcdef".to_string(),
+        )
+        .unwrap();
+        let data = header.clone().encode().unwrap();
+        assert_eq!(data[2..], *b"0123456789abcdef");
+        assert_eq!(Header::decode(data).unwrap(), header);
+
+        let empty = Header::new(HeaderIdentifier::NewPassword, 0, String::new()).unwrap();
+        assert_eq!(empty.clone().encode().unwrap()[2..], [0; 16]);
+        assert_eq!(
+            Header::decode(empty.clone().encode().unwrap()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn information_validation() {
+        let new = |information: &str| {
+            Header::new(HeaderIdentifier::Password, 1, information.to_string())
+                .unwrap_err()
+                .kind()
+        };
+        assert!(matches!(new("0123456789abcdefg"), ErrorKind::Encoding));
+        assert!(matches!(new("mot de passé"), ErrorKind::Encoding));
+        assert!(matches!(new("pass\0word"), ErrorKind::Encoding));
+    }
+
     #[test]
     fn header_identifier() {
         let order = [
@@ -1,18 +1,32 @@
 use async_trait::async_trait;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use std::{pin::Pin};
+use std::pin::Pin;
 pub trait Length {
     const LENGTH: usize;
+    /// Byte array holding an encoded value, always `[u8; Self::LENGTH]`
+    type Bytes: Bytes;
 }
+
+/// Fixed size byte buffer used by [`Decode`] and [`Encode`]
+pub trait Bytes: AsRef<[u8]> + AsMut<[u8]> + Send {
+    fn zeroed() -> Self;
+}
+
+impl<const N: usize> Bytes for [u8; N] {
+    fn zeroed() -> Self {
+        [0; N]
+    }
+}
+
 pub trait Decode: Sized + Length + Send {
     type Error: Send;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self, Self::Error>;
+    fn decode(data: Self::Bytes) -> Result<Self, Self::Error>;
 }
 
 pub trait Encode: Length {
     type Error;
-    fn encode(self) -> Result<[u8; Self::LENGTH], Self::Error>;
+    fn encode(self) -> Result<Self::Bytes, Self::Error>;
 }
 #[async_trait]
 pub trait DecodeFrom<R>: Sized + Send {
@@ -23,12 +37,11 @@ pub trait DecodeFrom<R>: Sized + Send {
 impl<R: AsyncReadExt + Send, D: Decode> DecodeFrom<R> for D
 where
     D::Error: From<std::io::Error>,
-    [(); Self::LENGTH]:,
 {
     type Error = D::Error;
     async fn decode_from(reader: &mut Pin<&mut R>) -> Result<Self, Self::Error> {
-        let mut buf = [0; Self::LENGTH];
-        reader.read_exact(&mut buf).await?;
+        let mut buf = D::Bytes::zeroed();
+        reader.read_exact(buf.as_mut()).await?;
         D::decode(buf)
     }
 }
@@ -43,24 +56,24 @@ pub trait EncodeTo<W>: Sized {
 impl<W: AsyncWriteExt + Send, E: Encode + Send> EncodeTo<W> for E
 where
     E::Error: From<std::io::Error> + Send,
-    [(); Self::LENGTH]:,
 {
     type Error = E::Error;
     async fn encode_to(self, writer: &mut Pin<&mut W>) -> Result<usize, Self::Error> {
-        writer.write_all(&self.encode()?).await?;
+        writer.write_all(self.encode()?.as_ref()).await?;
         Ok(E::LENGTH)
     }
 }
 
 impl<const N: usize> Decode for [u8; N] {
     type Error = crate::Error;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self, Self::Error> {
-        Ok(data[..N].try_into().unwrap())
+    fn decode(data: Self::Bytes) -> Result<Self, Self::Error> {
+        Ok(data)
     }
 }
 
-impl<T: Decode, const N: usize> Length for [T; N] {
-    const LENGTH: usize = T::LENGTH * N;
+impl<const N: usize> Length for [u8; N] {
+    const LENGTH: usize = N;
+    type Bytes = [u8; N];
 }
 
 #[macro_export]
@@ -69,21 +82,22 @@ macro_rules! number_io {
         $(
 
         impl Length for $type {
-            const LENGTH: usize = (Self::BITS / 8) as usize;
+            const LENGTH: usize = std::mem::size_of::<$type>();
+            type Bytes = [u8; std::mem::size_of::<$type>()];
         }
         impl Decode for $type {
             type Error = $crate::error::Error;
-            fn decode(data: [u8; Self::LENGTH]) -> Result<Self, Self::Error> {
+            fn decode(data: Self::Bytes) -> Result<Self, Self::Error> {
                 Ok(Self::from_be_bytes(data))
             }
         }
         impl Encode for $type {
             type Error = $crate::error::Error;
-            fn encode(self) -> Result<[u8;Self::LENGTH], Self::Error> {
+            fn encode(self) -> Result<Self::Bytes, Self::Error> {
                 Ok(self.to_be_bytes())
             }
         }
                 )*
     };
 }
-number_io!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, usize);
\ No newline at end of file
+number_io!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, usize);
@@ -0,0 +1,28 @@
+//! JSON representations shared by the command-line tool, the gateway and the MQTT bridge
+use serde_json::{json, Value};
+
+use crate::{client::Projector, control::ProjectorStatus};
+
+/// Discovered projector as `{"address", "name", "im_type", "command_type",
+/// "password_required"}`
+pub fn projector(projector: &Projector) -> Value {
+    json!({
+        "address": projector.addr().to_string(),
+        "name": projector.name(),
+        "im_type": projector.im_type(),
+        "command_type": projector.command_type().map(|c| format!("{c:?}")),
+        "password_required": projector.password_required(),
+    })
+}
+
+/// Status as `{"power", "input", "lamp_hours", "severity", "faults"}`, input and lamp
+/// hours being `null` when unavailable
+pub fn status(status: &ProjectorStatus) -> Value {
+    json!({
+        "power": status.power.to_string(),
+        "input": status.input,
+        "lamp_hours": status.lamp_hours,
+        "severity": format!("{:?}", status.severity),
+        "faults": status.faults,
+    })
+}
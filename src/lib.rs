@@ -1,11 +1,33 @@
-#![allow(incomplete_features)]
-#![feature(generic_const_exprs)]
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod client;
 pub mod command;
+pub mod commands;
+pub mod connection;
+pub mod control;
+pub mod discovery;
 pub mod error;
+pub mod fleet;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+pub mod handle;
 pub mod header;
 pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod monitor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "osc")]
+pub mod osc;
 pub mod packet;
+#[cfg(feature = "pjlink")]
+pub mod pjlink;
+pub mod power;
+pub mod reconnect;
+pub mod server;
 
 pub use error::Error;
 
@@ -0,0 +1,308 @@
+//! Prometheus metrics of projectors
+//!
+//! An [`Exporter`] polls projectors through [`Connections`], which it can share with a
+//! `gateway::Gateway` so that each projector keeps a single session, and exposes,
+//! labelled by `projector` name and `addr`:
+//!
+//! | Metric | Type |
+//! |--------|------|
+//! | `escvpnet_up` | gauge, 1 when the last poll reached the projector |
+//! | `escvpnet_power_state` | gauge, the `PWR?` code |
+//! | `escvpnet_lamp_hours` | gauge |
+//! | `escvpnet_filter_hours` | gauge, on models reporting it |
+//! | `escvpnet_error_code` | gauge, the `ERR?` code |
+//! | `escvpnet_command_duration_seconds` | histogram, also labelled by `command` |
+//! | `escvpnet_handshake_failures_total` | counter |
+//!
+//! [`Exporter::encode`] renders them in the Prometheus text format, served on `/metrics`
+//! by `router` with the `gateway` feature.
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::join_all;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{
+    client::{query_output, Projector},
+    commands::{ErrorCode, FilterHours, Lamp, Power, Query},
+    error::ErrorKind,
+    handle::{ClientHandle, Connections},
+    Result,
+};
+
+const LABELS: &[&str] = &["projector", "addr"];
+
+struct Target {
+    name: String,
+    addr: SocketAddr,
+}
+
+pub struct Exporter {
+    connections: Arc<Connections>,
+    targets: Vec<Target>,
+    registry: Registry,
+    up: IntGaugeVec,
+    power_state: IntGaugeVec,
+    lamp_hours: IntGaugeVec,
+    filter_hours: IntGaugeVec,
+    error_code: IntGaugeVec,
+    command_duration: HistogramVec,
+    handshake_failures: IntCounterVec,
+}
+
+impl Exporter {
+    /// Creates an exporter connecting with `password`, each connection and command
+    /// being limited to `timeout`
+    pub fn new(password: Option<String>, timeout: Duration) -> Self {
+        Self::with_connections(Arc::new(Connections::new(password, timeout)))
+    }
+
+    /// Creates an exporter polling through `connections`, such as those of a gateway
+    pub fn with_connections(connections: Arc<Connections>) -> Self {
+        let registry = Registry::new();
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), LABELS).expect("valid metric");
+            registry
+                .register(Box::new(gauge.clone()))
+                .expect("unique metric");
+            gauge
+        };
+        let up = gauge("escvpnet_up", "Whether the last poll reached the projector");
+        let power_state = gauge("escvpnet_power_state", "Power state code of PWR?");
+        let lamp_hours = gauge("escvpnet_lamp_hours", "Lamp hours");
+        let filter_hours = gauge("escvpnet_filter_hours", "Air filter hours");
+        let error_code = gauge("escvpnet_error_code", "Fault code of ERR?");
+        let command_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "escvpnet_command_duration_seconds",
+                "Duration of the commands",
+            ),
+            &["projector", "addr", "command"],
+        )
+        .expect("valid metric");
+        registry
+            .register(Box::new(command_duration.clone()))
+            .expect("unique metric");
+        let handshake_failures = IntCounterVec::new(
+            Opts::new(
+                "escvpnet_handshake_failures_total",
+                "Failed handshakes, such as with a wrong password",
+            ),
+            LABELS,
+        )
+        .expect("valid metric");
+        registry
+            .register(Box::new(handshake_failures.clone()))
+            .expect("unique metric");
+        Self {
+            connections,
+            targets: Vec::new(),
+            registry,
+            up,
+            power_state,
+            lamp_hours,
+            filter_hours,
+            error_code,
+            command_duration,
+            handshake_failures,
+        }
+    }
+
+    /// Adds a projector, labelled `name`
+    pub fn add(&mut self, addr: SocketAddr, name: String) {
+        self.targets.push(Target { name, addr });
+    }
+
+    /// Adds a discovered projector, labelled by its name or else its IP address
+    pub fn add_discovered(&mut self, projector: &Projector) {
+        let name = projector
+            .name()
+            .unwrap_or_else(|| projector.addr().ip().to_string());
+        self.add(projector.addr(), name);
+    }
+
+    /// Registry of the metrics, to gather them along with others
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn encode(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+
+    /// Polls every projector once, concurrently
+    pub async fn poll(&self) {
+        join_all(self.targets.iter().map(|target| self.poll_target(target))).await;
+    }
+
+    /// Polls every `interval`, never returning
+    pub async fn run(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    async fn poll_target(&self, target: &Target) {
+        let addr = target.addr.to_string();
+        let labels = [target.name.as_str(), addr.as_str()];
+        let result = match self.connections.get(&addr).await {
+            Ok(handle) => self.update(&handle, &labels).await,
+            Err(error) => {
+                // Errors other than IO ones are answers of the projector to the handshake
+                if !matches!(error.kind(), ErrorKind::IO(_)) {
+                    self.handshake_failures.with_label_values(&labels).inc();
+                }
+                Err(error)
+            }
+        };
+        match self.connections.check(&addr, result) {
+            Ok(()) => self.up.with_label_values(&labels).set(1),
+            Err(_) => {
+                self.up.with_label_values(&labels).set(0);
+                for gauge in [
+                    &self.power_state,
+                    &self.lamp_hours,
+                    &self.filter_hours,
+                    &self.error_code,
+                ] {
+                    let _ = gauge.remove_label_values(&labels);
+                }
+            }
+        }
+    }
+
+    /// Updates the gauges, failing only when the connection is lost
+    async fn update(&self, handle: &ClientHandle, labels: &[&str; 2]) -> Result<()> {
+        let power = self.query(handle, labels, Power).await;
+        record(
+            &self.power_state,
+            labels,
+            power.map(|power| power.code().into()),
+        )?;
+        let lamp = self.query(handle, labels, Lamp).await;
+        record(&self.lamp_hours, labels, lamp.map(i64::from))?;
+        let filter = self.query(handle, labels, FilterHours).await;
+        record(&self.filter_hours, labels, filter.map(i64::from))?;
+        let fault = self.query(handle, labels, ErrorCode).await;
+        record(
+            &self.error_code,
+            labels,
+            fault.map(|fault| fault.code().into()),
+        )
+    }
+
+    async fn query<Q: Query>(
+        &self,
+        handle: &ClientHandle,
+        labels: &[&str; 2],
+        query: Q,
+    ) -> Result<Q::Output> {
+        let started = Instant::now();
+        let response = handle.execute(query.get()).await;
+        self.command_duration
+            .with_label_values(&[labels[0], labels[1], Q::NAME])
+            .observe(started.elapsed().as_secs_f64());
+        query_output::<Q>(response?)
+    }
+}
+
+/// Sets a gauge, or removes it when the value is unavailable
+fn record(gauge: &IntGaugeVec, labels: &[&str; 2], value: Result<i64>) -> Result<()> {
+    match value {
+        Ok(value) => gauge.with_label_values(labels).set(value),
+        Err(error) if matches!(error.kind(), ErrorKind::IO(_)) => return Err(error),
+        Err(_) => {
+            let _ = gauge.remove_label_values(labels);
+        }
+    }
+    Ok(())
+}
+
+/// Router serving the metrics of `exporter` on `/metrics`
+#[cfg(feature = "gateway")]
+pub fn router(exporter: std::sync::Arc<Exporter>) -> axum::Router {
+    use axum::{extract::State, http::header, routing::get};
+
+    async fn metrics(
+        State(exporter): State<std::sync::Arc<Exporter>>,
+    ) -> impl axum::response::IntoResponse {
+        (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            exporter.encode(),
+        )
+    }
+
+    axum::Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(exporter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::testing::spawn_server;
+
+    const VALUES: &[(&str, &str)] = &[("PWR", "01"), ("LAMP", "1200"), ("ERR", "00")];
+
+    #[tokio::test]
+    async fn exporter() {
+        let (addr, _) = spawn_server(VALUES, None).await;
+        let (locked, _) = spawn_server(VALUES, Some("projector1")).await;
+        let mut exporter = Exporter::new(None, Duration::from_secs(1));
+        exporter.add(addr, "hall".to_string());
+        exporter.add(locked, "foyer".to_string());
+        exporter.poll().await;
+        exporter.poll().await;
+
+        let metrics = exporter.encode();
+        let hall = "projector=\"hall\"}";
+        let foyer = "projector=\"foyer\"}";
+        assert!(metrics.contains(&format!("escvpnet_up{{addr=\"{addr}\",{hall} 1")));
+        assert!(metrics.contains(&format!("escvpnet_power_state{{addr=\"{addr}\",{hall} 1")));
+        assert!(metrics.contains(&format!("escvpnet_lamp_hours{{addr=\"{addr}\",{hall} 1200")));
+        assert!(metrics.contains(&format!("escvpnet_error_code{{addr=\"{addr}\",{hall} 0")));
+        assert!(!metrics.contains("escvpnet_filter_hours{"));
+        assert!(metrics.contains(&format!(
+            "escvpnet_command_duration_seconds_count{{addr=\"{addr}\",command=\"LAMP\",{hall} 2"
+        )));
+        assert!(metrics.contains(&format!("escvpnet_up{{addr=\"{locked}\",{foyer} 0")));
+        assert!(metrics.contains(&format!(
+            "escvpnet_handshake_failures_total{{addr=\"{locked}\",{foyer} 2"
+        )));
+    }
+    #[cfg(feature = "gateway")]
+    #[tokio::test]
+    async fn gateway_connections() {
+        use crate::server::testing::{memory_server, spawn_single};
+
+        // Serves a single session, like projectors
+        let addr = spawn_single(memory_server(VALUES, None)).await;
+        let gateway = crate::gateway::Gateway::new(
+            None,
+            Duration::from_secs(1),
+            "127.0.0.1:0".to_string(),
+            "127.0.0.1:0".to_string(),
+        );
+        let handle = gateway.connections().get(&addr.to_string()).await.unwrap();
+        let mut exporter = Exporter::with_connections(gateway.connections());
+        exporter.add(addr, "hall".to_string());
+        exporter.poll().await;
+
+        let metrics = exporter.encode();
+        assert!(metrics.contains(&format!(
+            "escvpnet_up{{addr=\"{addr}\",projector=\"hall\"}} 1"
+        )));
+        assert_eq!(handle.get(Power).await.unwrap().code(), 1);
+    }
+}
@@ -0,0 +1,250 @@
+//! Periodic status polling
+//!
+//! [`Monitor`] queries a set of commands at a fixed interval, slower while the projector
+//! is in standby or unreachable, keeps their last known values and reports changes.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use futures_util::Stream;
+use tokio::{net::ToSocketAddrs, time::Instant};
+
+use crate::{
+    command::Command,
+    commands::{ErrorCode, Fault, Power, Query},
+    error::ErrorKind,
+    reconnect::{Backoff, ReconnectingClient},
+    Result,
+};
+
+#[derive(Debug)]
+pub enum MonitorEvent {
+    /// A query returned a new value, `old` being `None` the first time
+    Changed {
+        name: String,
+        old: Option<String>,
+        new: String,
+    },
+    /// `ERR?` started reporting a fault
+    FaultRaised(Fault),
+    /// A query started failing, its last known value being kept
+    Failed { name: String, error: crate::Error },
+    /// The projector could not be reached, polling continues at the standby interval
+    Unreachable(crate::Error),
+    /// The projector answered again after being unreachable
+    Reachable,
+}
+
+pub struct Monitor<A> {
+    client: ReconnectingClient<A>,
+    queries: Vec<String>,
+    interval: Duration,
+    standby_interval: Duration,
+    next_poll: Instant,
+    values: HashMap<String, String>,
+    failing: HashSet<String>,
+    reachable: Option<bool>,
+    polling: bool,
+    events: VecDeque<MonitorEvent>,
+}
+
+impl<A: ToSocketAddrs + Clone> Monitor<A> {
+    /// Polls the commands named `queries`, such as `PWR`, `SOURCE`, `LAMP` and `ERR`,
+    /// every `interval`.
+    ///
+    /// A failed connection is reported as [`MonitorEvent::Unreachable`] and attempted
+    /// again at the next poll, instead of following the backoff of `client`.
+    pub fn new<S: Into<String>>(
+        mut client: ReconnectingClient<A>,
+        queries: impl IntoIterator<Item = S>,
+        interval: Duration,
+    ) -> Self {
+        client.set_backoff(Backoff::SINGLE_ATTEMPT);
+        Self {
+            client,
+            queries: queries.into_iter().map(Into::into).collect(),
+            interval,
+            standby_interval: interval,
+            next_poll: Instant::now(),
+            values: HashMap::new(),
+            failing: HashSet::new(),
+            reachable: None,
+            polling: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Sets the interval while `PWR?` reports a standby state or the projector is unreachable
+    pub fn set_standby_interval(&mut self, standby_interval: Duration) {
+        self.standby_interval = standby_interval;
+    }
+
+    /// Last known value of a query
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn values(&self) -> &HashMap<String, String> {
+        &self.values
+    }
+
+    /// Waits for the next event, polling when the interval elapses.
+    ///
+    /// This method is cancel safe while waiting for the next poll, but cancelling it
+    /// during a poll makes the next one reconnect.
+    pub async fn next_event(&mut self) -> Result<MonitorEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            tokio::time::sleep_until(self.next_poll).await;
+            if self.polling {
+                // The previous poll was cancelled, possibly before reading its reply
+                self.client.disconnect();
+            }
+            self.polling = true;
+            self.poll().await;
+            self.polling = false;
+            self.next_poll = Instant::now() + self.current_interval();
+        }
+    }
+
+    /// Turns this monitor into a stream of events
+    pub fn into_stream(self) -> impl Stream<Item = Result<MonitorEvent>> {
+        futures_util::stream::unfold(self, |mut monitor| async move {
+            let event = monitor.next_event().await;
+            Some((event, monitor))
+        })
+    }
+
+    async fn poll(&mut self) {
+        for name in self.queries.clone() {
+            let command = Command::Get { name: name.clone() };
+            match self.client.execute(command).await {
+                Ok(response) => {
+                    self.set_reachable(true, None);
+                    self.failing.remove(&name);
+                    let Some(value) = response.value() else {
+                        continue;
+                    };
+                    self.update(name, value.to_string());
+                }
+                Err(error) if matches!(error.kind(), ErrorKind::IO(_)) => {
+                    self.set_reachable(false, Some(error));
+                    return;
+                }
+                Err(error) => {
+                    self.set_reachable(true, None);
+                    if self.failing.insert(name.clone()) {
+                        self.events.push_back(MonitorEvent::Failed { name, error });
+                    }
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, name: String, value: String) {
+        let old = self.values.insert(name.clone(), value.clone());
+        if old.as_ref() == Some(&value) {
+            return;
+        }
+        if name == ErrorCode::NAME {
+            match ErrorCode::parse(&value) {
+                Ok(Fault::None) | Err(_) => {}
+                Ok(fault) => self.events.push_back(MonitorEvent::FaultRaised(fault)),
+            }
+        }
+        self.events.push_back(MonitorEvent::Changed {
+            name,
+            old,
+            new: value,
+        });
+    }
+
+    fn set_reachable(&mut self, reachable: bool, error: Option<crate::Error>) {
+        if self.reachable == Some(reachable) {
+            return;
+        }
+        let was_unreachable = self.reachable == Some(false);
+        self.reachable = Some(reachable);
+        match error {
+            Some(error) => self.events.push_back(MonitorEvent::Unreachable(error)),
+            None if was_unreachable => self.events.push_back(MonitorEvent::Reachable),
+            None => {}
+        }
+    }
+
+    fn current_interval(&self) -> Duration {
+        let standby = self
+            .values
+            .get(Power::NAME)
+            .and_then(|value| Power::parse(value).ok())
+            .map_or(false, |state| state.is_standby());
+        if standby || self.reachable == Some(false) {
+            self.standby_interval
+        } else {
+            self.interval
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::server::{testing::spawn_server, Handler};
+
+    #[tokio::test]
+    async fn events() {
+        let (addr, server) = spawn_server(&[("PWR", "01"), ("ERR", "00")], None).await;
+
+        let client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        let mut monitor = Monitor::new(client, ["PWR", "ERR", "LAMP"], Duration::from_millis(10));
+
+        for name in ["PWR", "ERR"] {
+            match monitor.next_event().await.unwrap() {
+                MonitorEvent::Changed {
+                    name: n, old: None, ..
+                } => assert_eq!(n, name),
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+        match monitor.next_event().await.unwrap() {
+            MonitorEvent::Failed { name, .. } => assert_eq!(name, "LAMP"),
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        let set = "ERR 04".parse().unwrap();
+        server.handler().handle(set).await.unwrap();
+        match monitor.next_event().await.unwrap() {
+            MonitorEvent::FaultRaised(fault) => assert_eq!(fault, Fault::HighTemperature),
+            event => panic!("unexpected event {event:?}"),
+        }
+        match monitor.next_event().await.unwrap() {
+            MonitorEvent::Changed { name, old, new } => {
+                assert_eq!(name, "ERR");
+                assert_eq!(old.as_deref(), Some("00"));
+                assert_eq!(new, "04");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert_eq!(monitor.value("PWR"), Some("01"));
+    }
+
+    #[tokio::test]
+    async fn unreachable() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        let mut monitor = Monitor::new(client, ["PWR"], Duration::from_millis(10));
+        assert!(matches!(
+            monitor.next_event().await.unwrap(),
+            MonitorEvent::Unreachable(_)
+        ));
+    }
+}
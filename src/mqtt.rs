@@ -0,0 +1,665 @@
+//! MQTT bridge with Home Assistant discovery
+//!
+//! [`Bridge`] polls projectors and publishes their state, retained, as JSON to
+//! `{prefix}/{id}/state`, `id` being derived from the projector address. It executes the
+//! messages received on:
+//!
+//! - `{prefix}/{id}/power/set`, with `ON` or `OFF`
+//! - `{prefix}/{id}/source/set`, with a [`Source`] name or code
+//! - `{prefix}/{id}/command`, with an ESC/VP21 command such as `VOL?` or `SOURCE 30`,
+//!   answered on `{prefix}/{id}/response`
+//!
+//! Home Assistant discovery configs are published for the power switch, the source
+//! select, the lamp hours and the fault of every projector.
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use rumqttc::{
+    AsyncClient, ConnectReturnCode, ConnectionError, Event, EventLoop, LastWill, MqttOptions,
+    Packet, QoS,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    client::Projector,
+    command::Command,
+    commands::{Control, Power, SelectSource, Source, Switch},
+    control::ProjectorControl,
+    error::ErrorKind,
+    reconnect::ReconnectingClient,
+    Result,
+};
+
+const DEFAULT_TOPIC_PREFIX: &str = "escvpnet";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+const QUEUE_SIZE: usize = 64;
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+struct BridgedProjector {
+    name: String,
+    client: ReconnectingClient<SocketAddr>,
+    online: Option<bool>,
+}
+
+pub struct Bridge {
+    options: MqttOptions,
+    topic_prefix: String,
+    discovery_prefix: String,
+    password: Option<String>,
+    timeout: Duration,
+    interval: Duration,
+    reconnect_delay: Duration,
+    projectors: HashMap<String, BridgedProjector>,
+}
+
+impl Bridge {
+    /// Creates a bridge to the broker of `options`, connecting to projectors with
+    /// `password` and polling them every `interval`
+    pub fn new(
+        options: MqttOptions,
+        password: Option<String>,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            options,
+            topic_prefix: DEFAULT_TOPIC_PREFIX.to_string(),
+            discovery_prefix: DEFAULT_DISCOVERY_PREFIX.to_string(),
+            password,
+            timeout,
+            interval,
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+            projectors: HashMap::new(),
+        }
+    }
+
+    /// Sets the prefix of state and command topics, `escvpnet` by default
+    pub fn set_topic_prefix(&mut self, topic_prefix: String) {
+        self.topic_prefix = topic_prefix;
+    }
+
+    /// Sets the Home Assistant discovery prefix, `homeassistant` by default
+    pub fn set_discovery_prefix(&mut self, discovery_prefix: String) {
+        self.discovery_prefix = discovery_prefix;
+    }
+
+    /// Sets the delay before connecting to the broker again after a connection error,
+    /// 5 seconds by default
+    pub fn set_reconnect_delay(&mut self, reconnect_delay: Duration) {
+        self.reconnect_delay = reconnect_delay;
+    }
+
+    /// Adds a projector found by [`Client::discover`](crate::client::Client::discover)
+    pub fn add_discovered(&mut self, projector: &Projector) {
+        let name = projector
+            .name()
+            .unwrap_or_else(|| projector.addr().ip().to_string());
+        self.add(projector.addr(), name);
+    }
+
+    /// Adds a projector, named `name` in Home Assistant
+    pub fn add(&mut self, addr: SocketAddr, name: String) {
+        // Unreachable projectors are reported offline and attempted again at the next poll
+        let client = ReconnectingClient::polled(addr, self.password.clone(), self.timeout);
+        let projector = BridgedProjector {
+            name,
+            client,
+            online: None,
+        };
+        self.projectors.insert(projector_id(addr), projector);
+    }
+
+    /// Runs the bridge until a fatal MQTT error, such as refused credentials.
+    ///
+    /// The broker connection is established again after other errors, and
+    /// subscriptions and discovery configs are sent again on every broker connection.
+    pub async fn run(mut self) -> Result<()> {
+        let availability = self.topic("bridge", "availability");
+        let mut options = self.options.clone();
+        options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, event_loop) = AsyncClient::new(options, QUEUE_SIZE);
+        // Driven in its own task to keep the broker connection alive while polling
+        let (sender, mut events) = tokio::sync::mpsc::channel(QUEUE_SIZE);
+        let driver = tokio::spawn(drive(event_loop, sender, self.reconnect_delay));
+        let mut interval = tokio::time::interval(self.interval);
+        let result = loop {
+            let result = tokio::select! {
+                event = events.recv() => match event {
+                    Some(Ok(Packet::ConnAck(_))) => self.announce(&client).await,
+                    Some(Ok(Packet::Publish(publish))) => {
+                        self.receive(&client, &publish.topic, &publish.payload).await
+                    }
+                    Some(Ok(_)) => Ok(()),
+                    Some(Err(error)) => Err(error),
+                    None => Err(mqtt_error("event loop stopped")),
+                },
+                _ = interval.tick() => self.publish_states(&client).await,
+            };
+            if let Err(error) = result {
+                break Err(error);
+            }
+        };
+        driver.abort();
+        result
+    }
+
+    /// Subscribes to command topics and publishes availability and discovery configs
+    async fn announce(&self, client: &AsyncClient) -> Result<()> {
+        let prefix = &self.topic_prefix;
+        for topic in ["+/power/set", "+/source/set", "+/command"] {
+            client
+                .subscribe(format!("{prefix}/{topic}"), QoS::AtLeastOnce)
+                .await
+                .map_err(mqtt_error)?;
+        }
+        publish(client, self.topic("bridge", "availability"), "online").await?;
+        for (id, projector) in &self.projectors {
+            for (topic, config) in
+                discovery_configs(&self.discovery_prefix, prefix, id, &projector.name)
+            {
+                publish(client, topic, config.to_string()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self, client: &AsyncClient, topic: &str, payload: &[u8]) -> Result<()> {
+        let Some((id, command)) = parse_command(&self.topic_prefix, topic, payload) else {
+            return Ok(());
+        };
+        let raw = topic.ends_with("/command");
+        let response_topic = self.topic(&id, "response");
+        let Some(projector) = self.projectors.get_mut(&id) else {
+            return Ok(());
+        };
+        let result = match command {
+            Ok(command) => projector.client.execute(command).await,
+            Err(error) => Err(error),
+        };
+        if raw {
+            let response = match result {
+                Ok(response) => json!({ "name": response.name(), "value": response.value() }),
+                Err(error) => {
+                    json!({ "error": error.kind().to_string(), "message": error.message() })
+                }
+            };
+            client
+                .publish(
+                    response_topic,
+                    QoS::AtLeastOnce,
+                    false,
+                    response.to_string(),
+                )
+                .await
+                .map_err(mqtt_error)?;
+        }
+        self.publish_state(client, &id).await
+    }
+
+    async fn publish_states(&mut self, client: &AsyncClient) -> Result<()> {
+        let ids: Vec<String> = self.projectors.keys().cloned().collect();
+        for id in ids {
+            self.publish_state(client, &id).await?;
+        }
+        Ok(())
+    }
+
+    async fn publish_state(&mut self, client: &AsyncClient, id: &str) -> Result<()> {
+        let state_topic = self.topic(id, "state");
+        let availability_topic = self.topic(id, "availability");
+        let Some(projector) = self.projectors.get_mut(id) else {
+            return Ok(());
+        };
+        let (online, state) = match state_payload(&mut projector.client).await {
+            Ok(state) => (true, Some(state)),
+            Err(error) if matches!(error.kind(), ErrorKind::IO(_)) => (false, None),
+            Err(_) => (true, None),
+        };
+        if let Some(state) = state {
+            publish(client, state_topic, state.to_string()).await?;
+        }
+        if projector.online != Some(online) {
+            projector.online = Some(online);
+            let availability = if online { "online" } else { "offline" };
+            publish(client, availability_topic, availability).await?;
+        }
+        Ok(())
+    }
+
+    fn topic(&self, id: &str, topic: &str) -> String {
+        format!("{}/{id}/{topic}", self.topic_prefix)
+    }
+}
+
+/// Forwards incoming packets, connecting again `reconnect_delay` after connection
+/// errors, until a fatal error
+async fn drive(
+    mut event_loop: EventLoop,
+    sender: tokio::sync::mpsc::Sender<Result<Packet>>,
+    reconnect_delay: Duration,
+) {
+    loop {
+        let packet = match event_loop.poll().await {
+            Ok(Event::Incoming(packet)) => packet,
+            Ok(Event::Outgoing(_)) => continue,
+            Err(error) if is_fatal(&error) => {
+                let _ = sender.send(Err(mqtt_error(error))).await;
+                return;
+            }
+            Err(error) => {
+                eprintln!(
+                    "MQTT connection failed, reconnecting in {}s: {error}",
+                    reconnect_delay.as_secs_f32()
+                );
+                tokio::time::sleep(reconnect_delay).await;
+                continue;
+            }
+        };
+        if sender.send(Ok(packet)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Whether connecting again cannot succeed, as the broker refuses the bridge
+fn is_fatal(error: &ConnectionError) -> bool {
+    matches!(
+        error,
+        ConnectionError::ConnectionRefused(
+            ConnectReturnCode::RefusedProtocolVersion
+                | ConnectReturnCode::BadClientId
+                | ConnectReturnCode::BadUserNamePassword
+                | ConnectReturnCode::NotAuthorized
+        ) | ConnectionError::RequestsDone
+    )
+}
+
+/// Publishes a retained message
+async fn publish(client: &AsyncClient, topic: String, payload: impl Into<Vec<u8>>) -> Result<()> {
+    client
+        .publish(topic, QoS::AtLeastOnce, true, payload)
+        .await
+        .map_err(mqtt_error)
+}
+
+fn mqtt_error(error: impl std::fmt::Display) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::IO(std::io::ErrorKind::Other),
+        format!("MQTT error: {error}"),
+    )
+}
+
+/// Topic segment identifying a projector, such as `192_168_0_1_3629`
+pub fn projector_id(addr: SocketAddr) -> String {
+    addr.to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
+
+/// State published for a projector, as built by [`crate::json::status`]
+pub async fn state_payload(client: &mut ReconnectingClient<SocketAddr>) -> Result<Value> {
+    Ok(crate::json::status(&client.status().await?))
+}
+
+/// Projector id and command for a message on a command topic, `None` for other topics
+pub fn parse_command(
+    topic_prefix: &str,
+    topic: &str,
+    payload: &[u8],
+) -> Option<(String, Result<Command>)> {
+    let rest = topic.strip_prefix(topic_prefix)?.strip_prefix('/')?;
+    let (id, action) = rest.split_once('/')?;
+    let payload = String::from_utf8_lossy(payload);
+    let payload = payload.trim();
+    let command = match action {
+        "power/set" => payload.parse::<Switch>().map(|switch| Power.set(switch)),
+        "source/set" => payload
+            .parse::<Source>()
+            .map(|source| SelectSource.set(source)),
+        "command" => payload.parse(),
+        _ => return None,
+    };
+    Some((id.to_string(), command))
+}
+
+/// Home Assistant discovery topics and configs for a projector
+pub fn discovery_configs(
+    discovery_prefix: &str,
+    topic_prefix: &str,
+    id: &str,
+    name: &str,
+) -> Vec<(String, Value)> {
+    let unique_id = format!("escvpnet_{id}");
+    let topic = |topic: &str| format!("{topic_prefix}/{id}/{topic}");
+    let common = json!({
+        "state_topic": topic("state"),
+        "availability": [
+            { "topic": format!("{topic_prefix}/bridge/availability") },
+            { "topic": topic("availability") },
+        ],
+        "availability_mode": "all",
+        "device": {
+            "identifiers": [unique_id],
+            "name": name,
+            "manufacturer": "Epson",
+        },
+    });
+    let source_options: Vec<String> = Source::VARIANTS
+        .iter()
+        .map(|source| source.to_string())
+        .collect();
+    let entities = [
+        (
+            "switch",
+            "power",
+            json!({
+                "name": "Power",
+                "value_template": "{{ 'ON' if value_json.power in ['On', 'Warmup'] else 'OFF' }}",
+                "command_topic": topic("power/set"),
+                "payload_on": "ON",
+                "payload_off": "OFF",
+            }),
+        ),
+        (
+            "select",
+            "source",
+            json!({
+                "name": "Source",
+                "value_template": "{{ value_json.input }}",
+                "command_topic": topic("source/set"),
+                "options": source_options,
+            }),
+        ),
+        (
+            "sensor",
+            "lamp_hours",
+            json!({
+                "name": "Lamp hours",
+                "value_template": "{{ value_json.lamp_hours }}",
+                "unit_of_measurement": "h",
+                "state_class": "total_increasing",
+            }),
+        ),
+        (
+            "sensor",
+            "fault",
+            json!({
+                "name": "Fault",
+                "value_template": "{{ value_json.faults | join(', ') or 'No error' }}",
+            }),
+        ),
+        (
+            "binary_sensor",
+            "problem",
+            json!({
+                "name": "Problem",
+                "device_class": "problem",
+                "value_template": "{{ 'ON' if value_json.severity != 'None' else 'OFF' }}",
+            }),
+        ),
+    ];
+    entities
+        .into_iter()
+        .map(|(component, object_id, mut config)| {
+            let fields = config.as_object_mut().expect("configs are objects");
+            fields.insert(
+                "unique_id".to_string(),
+                json!(format!("{unique_id}_{object_id}")),
+            );
+            for (key, value) in common.as_object().expect("configs are objects") {
+                fields.insert(key.clone(), value.clone());
+            }
+            (
+                format!("{discovery_prefix}/{component}/{unique_id}/{object_id}/config"),
+                config,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, PubAck, Publish, SubAck, SubscribeReasonCode};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::server::testing::spawn_server;
+
+    const MAX_SIZE: usize = 64 * 1024;
+
+    /// Broker side of a single MQTT connection, acknowledging what the bridge sends
+    struct Broker {
+        stream: TcpStream,
+        buf: BytesMut,
+        retained: HashMap<String, Value>,
+    }
+
+    impl Broker {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut broker = Self {
+                stream,
+                buf: BytesMut::new(),
+                retained: HashMap::new(),
+            };
+            assert!(matches!(broker.read().await, Packet::Connect(_)));
+            broker
+                .write(Packet::ConnAck(ConnAck::new(
+                    ConnectReturnCode::Success,
+                    false,
+                )))
+                .await;
+            broker
+        }
+
+        async fn read(&mut self) -> Packet {
+            loop {
+                match Packet::read(&mut self.buf, MAX_SIZE) {
+                    Ok(packet) => return packet,
+                    Err(rumqttc::Error::InsufficientBytes(_)) => {}
+                    Err(error) => panic!("{error:?}"),
+                }
+                assert_ne!(self.stream.read_buf(&mut self.buf).await.unwrap(), 0);
+            }
+        }
+
+        async fn write(&mut self, packet: Packet) {
+            let mut buf = BytesMut::new();
+            packet.write(&mut buf, MAX_SIZE).unwrap();
+            self.stream.write_all(&buf).await.unwrap();
+        }
+
+        /// Payload of the last retained message on `topic`, waiting for one if needed
+        async fn retained(&mut self, topic: &str) -> Value {
+            match self.retained.get(topic) {
+                Some(payload) => payload.clone(),
+                None => self.published(topic).await,
+            }
+        }
+
+        /// Payload of the next message published to `topic`, keeping the retained ones
+        async fn published(&mut self, topic: &str) -> Value {
+            loop {
+                match self.read().await {
+                    Packet::Subscribe(subscribe) => {
+                        let codes = vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)];
+                        self.write(Packet::SubAck(SubAck::new(subscribe.pkid, codes)))
+                            .await;
+                    }
+                    Packet::Publish(publish) => {
+                        if publish.qos == QoS::AtLeastOnce {
+                            self.write(Packet::PubAck(PubAck::new(publish.pkid))).await;
+                        }
+                        let payload = serde_json::from_slice(&publish.payload)
+                            .unwrap_or_else(|_| json!(String::from_utf8_lossy(&publish.payload)));
+                        if publish.retain {
+                            self.retained.insert(publish.topic.clone(), payload.clone());
+                        }
+                        if publish.topic == topic {
+                            return payload;
+                        }
+                    }
+                    Packet::PingReq => self.write(Packet::PingResp).await,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let addr = "192.168.0.1:3629".parse().unwrap();
+        let id = projector_id(addr);
+        assert_eq!(id, "192_168_0_1_3629");
+
+        let (command_id, command) =
+            parse_command("escvpnet", &format!("escvpnet/{id}/power/set"), b"ON").unwrap();
+        assert_eq!(command_id, id);
+        assert_eq!(command.unwrap(), "PWR ON".parse().unwrap());
+        let (_, command) =
+            parse_command("escvpnet", &format!("escvpnet/{id}/source/set"), b"hdmi1").unwrap();
+        assert_eq!(command.unwrap(), "SOURCE 30".parse().unwrap());
+        let (_, command) =
+            parse_command("escvpnet", &format!("escvpnet/{id}/command"), b"VOL?\n").unwrap();
+        assert_eq!(command.unwrap(), "VOL?".parse().unwrap());
+        let (_, command) =
+            parse_command("escvpnet", &format!("escvpnet/{id}/power/set"), b"maybe").unwrap();
+        assert!(command.is_err());
+
+        assert!(parse_command("escvpnet", &format!("escvpnet/{id}/state"), b"{}").is_none());
+        assert!(parse_command("escvpnet", "other/power/set", b"ON").is_none());
+    }
+
+    #[test]
+    fn discovery() {
+        let configs = discovery_configs("homeassistant", "escvpnet", "10_0_0_2_3629", "Hall");
+        let (topic, power) = &configs[0];
+        assert_eq!(
+            topic,
+            "homeassistant/switch/escvpnet_10_0_0_2_3629/power/config"
+        );
+        assert_eq!(power["command_topic"], "escvpnet/10_0_0_2_3629/power/set");
+        assert_eq!(power["state_topic"], "escvpnet/10_0_0_2_3629/state");
+        assert_eq!(power["unique_id"], "escvpnet_10_0_0_2_3629_power");
+        assert_eq!(power["device"]["name"], "Hall");
+        let (_, source) = &configs[1];
+        assert_eq!(source["options"][2], "Hdmi1");
+    }
+
+    #[tokio::test]
+    async fn state() {
+        let values = [("PWR", "01"), ("LAMP", "1200"), ("ERR", "00")];
+        let (addr, _) = spawn_server(&values, None).await;
+
+        let mut client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        let state = state_payload(&mut client).await.unwrap();
+        assert_eq!(
+            state,
+            json!({
+                "power": "On",
+                "input": null,
+                "lamp_hours": 1200,
+                "severity": "None",
+                "faults": [],
+            })
+        );
+    }
+    #[tokio::test]
+    async fn bridge() {
+        let values = [("PWR", "01"), ("LAMP", "1200"), ("ERR", "00")];
+        let (addr, _) = spawn_server(&values, None).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = MqttOptions::new("escvpnet", "127.0.0.1", port);
+        let mut bridge = Bridge::new(
+            options,
+            None,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+        );
+        bridge.set_reconnect_delay(Duration::from_millis(100));
+        bridge.add(addr, "Hall".to_string());
+        let bridge = tokio::spawn(bridge.run());
+        let id = projector_id(addr);
+
+        let mut broker = Broker::accept(&listener).await;
+        // The first poll and the announcement on connection are not ordered
+        let state = broker.retained(&format!("escvpnet/{id}/state")).await;
+        assert_eq!(state["power"], "On");
+        assert_eq!(state["lamp_hours"], 1200);
+        let config = format!("homeassistant/switch/escvpnet_{id}/power/config");
+        let power = broker.retained(&config).await;
+        assert_eq!(power["command_topic"], format!("escvpnet/{id}/power/set"));
+        let availability = broker.retained("escvpnet/bridge/availability").await;
+        assert_eq!(availability, "online");
+        let availability = broker
+            .retained(&format!("escvpnet/{id}/availability"))
+            .await;
+        assert_eq!(availability, "online");
+
+        let command = Publish::new(format!("escvpnet/{id}/command"), QoS::AtMostOnce, "PWR?");
+        broker.write(Packet::Publish(command)).await;
+        let response = broker.published(&format!("escvpnet/{id}/response")).await;
+        assert_eq!(response, json!({ "name": "PWR", "value": "01" }));
+        let state = broker.published(&format!("escvpnet/{id}/state")).await;
+        assert_eq!(state["input"], Value::Null);
+        let command = Publish::new(
+            format!("escvpnet/{id}/source/set"),
+            QoS::AtMostOnce,
+            "hdmi2",
+        );
+        broker.write(Packet::Publish(command)).await;
+        let state = broker.published(&format!("escvpnet/{id}/state")).await;
+        assert_eq!(state["input"], "Hdmi2");
+
+        // A restarted broker gets the subscriptions and discovery configs again
+        drop(broker);
+        let mut broker = Broker::accept(&listener).await;
+        let power = broker.retained(&config).await;
+        assert_eq!(power["command_topic"], format!("escvpnet/{id}/power/set"));
+        let availability = broker.retained("escvpnet/bridge/availability").await;
+        assert_eq!(availability, "online");
+        let command = Publish::new(format!("escvpnet/{id}/command"), QoS::AtMostOnce, "LAMP?");
+        broker.write(Packet::Publish(command)).await;
+        let response = broker.published(&format!("escvpnet/{id}/response")).await;
+        assert_eq!(response, json!({ "name": "LAMP", "value": "1200" }));
+        assert!(!bridge.is_finished());
+        bridge.abort();
+    }
+
+    #[tokio::test]
+    async fn refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = MqttOptions::new("escvpnet", "127.0.0.1", port);
+        let bridge = Bridge::new(
+            options,
+            None,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+        );
+        let bridge = tokio::spawn(bridge.run());
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut broker = Broker {
+            stream,
+            buf: BytesMut::new(),
+            retained: HashMap::new(),
+        };
+        assert!(matches!(broker.read().await, Packet::Connect(_)));
+        let refusal = ConnAck::new(ConnectReturnCode::NotAuthorized, false);
+        broker.write(Packet::ConnAck(refusal)).await;
+        assert!(bridge.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,690 @@
+//! Open Sound Control bridge for show control
+//!
+//! [`Bridge`] receives OSC messages over UDP, from show controllers such as QLab or
+//! lighting consoles, addressed to a projector by name:
+//!
+//! - `/projector/{name}/power`, `/mute` and `/freeze`, with `on`, `off`, `1`, `0` or a
+//!   boolean
+//! - `/projector/{name}/source`, with a [`Source`] name or code
+//! - `/projector/{name}/volume`, with the projector's raw 0-255 value
+//! - `/projector/{name}/command`, with an ESC/VP21 command such as `VOL?` or `SOURCE 30`,
+//!   answered on `/projector/{name}/response`
+//! - `/projector/{name}/state`, answered with every value below
+//!
+//! Projectors are polled, and changes of their `power`, `source`, `mute`, `fault` and
+//! `online` values are sent as `/projector/{name}/{value}` messages to the peers added
+//! with [`Bridge::add_peer`], and to the last 16 peers that sent a message to a projector
+//! within the hour. Failed commands are answered on `/projector/{name}/error`.
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex as SyncMutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use crate::{
+    client::Projector,
+    command::{Command, Response},
+    commands::{
+        Control, ErrorCode, Freeze, Mute, Power, Query, SelectSource, Source, Switch, Volume,
+    },
+    error::ErrorKind,
+    reconnect::ReconnectingClient,
+    Result,
+};
+
+const BUF_SIZE: usize = 8192;
+const QUEUE_SIZE: usize = 32;
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+const MAX_SENDERS: usize = 16;
+const SENDER_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Argument of an OSC message, of the types of OSC 1.0 and booleans
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+impl Argument {
+    fn to_switch(&self) -> Result<Switch> {
+        match self {
+            Self::Int(0) | Self::Bool(false) => Ok(Switch::Off),
+            Self::Int(_) | Self::Bool(true) => Ok(Switch::On),
+            Self::Float(value) if *value == 0.0 => Ok(Switch::Off),
+            Self::Float(_) => Ok(Switch::On),
+            Self::String(value) => match value.as_str() {
+                "0" => Ok(Switch::Off),
+                "1" => Ok(Switch::On),
+                value => value.parse(),
+            },
+        }
+    }
+
+    fn to_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some((*value).into()),
+            Self::Float(value) => Some(value.round() as i64),
+            Self::String(value) => value.parse().ok(),
+            Self::Bool(value) => Some((*value).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub arguments: Vec<Argument>,
+}
+
+impl Message {
+    pub fn new(address: String, arguments: Vec<Argument>) -> Self {
+        Self { address, arguments }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_string(&mut buf, &self.address);
+        let tags: String = std::iter::once(',')
+            .chain(self.arguments.iter().map(|argument| match argument {
+                Argument::Int(_) => 'i',
+                Argument::Float(_) => 'f',
+                Argument::String(_) => 's',
+                Argument::Bool(true) => 'T',
+                Argument::Bool(false) => 'F',
+            }))
+            .collect();
+        write_string(&mut buf, &tags);
+        for argument in &self.arguments {
+            match argument {
+                Argument::Int(value) => buf.extend(value.to_be_bytes()),
+                Argument::Float(value) => buf.extend(value.to_be_bytes()),
+                Argument::String(value) => write_string(&mut buf, value),
+                Argument::Bool(_) => {}
+            }
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let address = read_string(buf, &mut pos)?;
+        if !address.starts_with('/') {
+            return Err(decoding_error("address must start with '/'"));
+        }
+        // Type tags may be omitted by old implementations when there is no argument
+        if pos >= buf.len() {
+            return Ok(Self::new(address, Vec::new()));
+        }
+        let tags = read_string(buf, &mut pos)?;
+        let tags = tags
+            .strip_prefix(',')
+            .ok_or_else(|| decoding_error("type tags must start with ','"))?;
+        let arguments = tags
+            .chars()
+            .map(|tag| match tag {
+                'i' => Ok(Argument::Int(i32::from_be_bytes(read_bytes(
+                    buf, &mut pos,
+                )?))),
+                'f' => Ok(Argument::Float(f32::from_be_bytes(read_bytes(
+                    buf, &mut pos,
+                )?))),
+                's' => Ok(Argument::String(read_string(buf, &mut pos)?)),
+                'T' => Ok(Argument::Bool(true)),
+                'F' => Ok(Argument::Bool(false)),
+                tag => Err(decoding_error(&format!("unsupported type tag '{tag}'"))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::new(address, arguments))
+    }
+}
+
+/// Messages of a packet, either a message or a bundle of them
+pub fn decode_packet(buf: &[u8]) -> Result<Vec<Message>> {
+    let Some(elements) = buf.strip_prefix(BUNDLE_TAG) else {
+        return Ok(vec![Message::decode(buf)?]);
+    };
+    // Time tag, messages being executed immediately
+    let mut pos = 8;
+    let mut messages = Vec::new();
+    while pos < elements.len() {
+        let size = u32::from_be_bytes(read_bytes(elements, &mut pos)?) as usize;
+        let element = elements
+            .get(pos..pos + size)
+            .ok_or_else(|| decoding_error("truncated bundle element"))?;
+        messages.extend(decode_packet(element)?);
+        pos += size;
+    }
+    Ok(messages)
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend(value.as_bytes());
+    buf.push(0);
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+}
+
+fn read_string(buf: &[u8], pos: &mut usize) -> Result<String> {
+    let rest = buf.get(*pos..).unwrap_or_default();
+    let end = rest
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| decoding_error("unterminated string"))?;
+    let value = String::from_utf8(rest[..end].to_vec())?;
+    *pos += (end + 4) & !3;
+    Ok(value)
+}
+
+fn read_bytes(buf: &[u8], pos: &mut usize) -> Result<[u8; 4]> {
+    let bytes = buf
+        .get(*pos..*pos + 4)
+        .ok_or_else(|| decoding_error("truncated argument"))?;
+    *pos += 4;
+    Ok(bytes.try_into().expect("4 bytes"))
+}
+
+fn decoding_error(message: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Decoding,
+        format!("Invalid OSC packet: {message}"),
+    )
+}
+
+/// Message to a projector: its sender, the action and the arguments
+type Request = (SocketAddr, String, Vec<Argument>);
+
+struct BridgedProjector {
+    name: String,
+    addr: SocketAddr,
+    client: ReconnectingClient<SocketAddr>,
+    state: HashMap<&'static str, Argument>,
+}
+
+pub struct Bridge {
+    password: Option<String>,
+    timeout: Duration,
+    interval: Duration,
+    projectors: HashMap<String, BridgedProjector>,
+    peers: Peers,
+}
+
+/// Peers receiving state changes: the added ones, and the latest senders of messages,
+/// forgotten after [`SENDER_TIMEOUT`] without one so that spoofed sources do not pile up
+#[derive(Default)]
+struct Peers {
+    added: Vec<SocketAddr>,
+    senders: Vec<(SocketAddr, Instant)>,
+}
+
+impl Bridge {
+    /// Creates a bridge connecting to projectors with `password` and polling them every
+    /// `interval`
+    pub fn new(password: Option<String>, timeout: Duration, interval: Duration) -> Self {
+        Self {
+            password,
+            timeout,
+            interval,
+            projectors: HashMap::new(),
+            peers: Peers::default(),
+        }
+    }
+
+    /// Adds a projector found by [`Client::discover`](crate::client::Client::discover),
+    /// named by its `ProjectorName` header or else its IP address, and returns the name
+    /// given by [`Bridge::add`]
+    pub fn add_discovered(&mut self, projector: &Projector) -> String {
+        let name = projector
+            .name()
+            .unwrap_or_else(|| projector.addr().ip().to_string());
+        self.add(projector.addr(), &name)
+    }
+
+    /// Adds a projector, addressed by `name` as sanitized by [`address_name`], and returns
+    /// the name.
+    ///
+    /// A projector at another address with the same name is not replaced, the new one
+    /// being named `{name}_{ip}`, or `{name}_{ip}_{port}`, instead.
+    pub fn add(&mut self, addr: SocketAddr, name: &str) -> String {
+        let name = [
+            name.to_string(),
+            format!("{name}_{}", addr.ip()),
+            format!("{name}_{}_{}", addr.ip(), addr.port()),
+        ]
+        .iter()
+        .map(|name| address_name(name))
+        .find(|name| {
+            self.projectors
+                .get(name)
+                .map_or(true, |projector| projector.addr == addr)
+        })
+        .expect("unique name for the address");
+        // Unreachable projectors are reported offline and attempted again at the next poll
+        let client = ReconnectingClient::polled(addr, self.password.clone(), self.timeout);
+        let projector = BridgedProjector {
+            name: name.clone(),
+            addr,
+            client,
+            state: HashMap::new(),
+        };
+        self.projectors.insert(name.clone(), projector);
+        name
+    }
+
+    /// Adds a peer receiving state changes without having to send a message first
+    pub fn add_peer(&mut self, peer: SocketAddr) {
+        if !self.peers.added.contains(&peer) {
+            self.peers.added.push(peer);
+        }
+    }
+
+    /// Runs the bridge on `socket` until receiving fails.
+    ///
+    /// Every projector is polled, and executes its messages in order, in its own task, so
+    /// that a slow or unreachable projector holds up neither the others nor the reading
+    /// of messages.
+    pub async fn run(self, socket: UdpSocket) -> Result<()> {
+        let socket = Arc::new(socket);
+        let peers = Arc::new(SyncMutex::new(self.peers));
+        let mut senders = HashMap::new();
+        let mut tasks = Vec::new();
+        for (name, projector) in self.projectors {
+            let (sender, requests) = mpsc::channel(QUEUE_SIZE);
+            senders.insert(name, sender);
+            let task = projector.run(socket.clone(), peers.clone(), requests, self.interval);
+            tasks.push(tokio::spawn(task));
+        }
+        let mut buf = [0; BUF_SIZE];
+        let result = loop {
+            let (n, peer) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(error) => break Err(error.into()),
+            };
+            // Malformed packets are ignored, UDP having no way to report them
+            let Ok(messages) = decode_packet(&buf[..n]) else {
+                continue;
+            };
+            // Only senders of messages to a projector receive its state changes
+            let known = messages.iter().any(|message| {
+                route(&message.address).map_or(false, |(name, _)| senders.contains_key(name))
+            });
+            if known {
+                let mut peers = peers.lock().expect("no panic while locked");
+                peers.received(peer, Instant::now());
+            }
+            for message in messages {
+                let Some((name, action)) = route(&message.address) else {
+                    continue;
+                };
+                // Messages to a projector still busy with a full queue are dropped
+                if let Some(sender) = senders.get(name) {
+                    let _ = sender.try_send((peer, action.to_string(), message.arguments));
+                }
+            }
+        };
+        for task in tasks {
+            task.abort();
+        }
+        result
+    }
+}
+
+impl Peers {
+    /// Records a message from `peer`, forgetting the least recent sender when there are
+    /// [`MAX_SENDERS`]
+    fn received(&mut self, peer: SocketAddr, now: Instant) {
+        if self.added.contains(&peer) {
+            return;
+        }
+        self.senders.retain(|(sender, _)| *sender != peer);
+        if self.senders.len() == MAX_SENDERS {
+            self.senders.remove(0);
+        }
+        self.senders.push((peer, now));
+    }
+
+    /// Peers to send state changes to, forgetting the expired senders
+    fn current(&mut self, now: Instant) -> Vec<SocketAddr> {
+        self.senders
+            .retain(|(_, received)| now.duration_since(*received) < SENDER_TIMEOUT);
+        let senders = self.senders.iter().map(|(sender, _)| *sender);
+        self.added.iter().copied().chain(senders).collect()
+    }
+}
+
+impl BridgedProjector {
+    async fn run(
+        mut self,
+        socket: Arc<UdpSocket>,
+        peers: Arc<SyncMutex<Peers>>,
+        mut requests: mpsc::Receiver<Request>,
+        interval: Duration,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                request = requests.recv() => {
+                    let Some((peer, action, arguments)) = request else {
+                        return;
+                    };
+                    self.receive(&socket, &peers, peer, &action, &arguments).await;
+                }
+                _ = interval.tick() => self.update(&socket, &peers).await,
+            }
+        }
+    }
+
+    async fn receive(
+        &mut self,
+        socket: &UdpSocket,
+        peers: &SyncMutex<Peers>,
+        peer: SocketAddr,
+        action: &str,
+        arguments: &[Argument],
+    ) {
+        if action == "state" {
+            self.update(socket, peers).await;
+            for (key, value) in &self.state {
+                send(socket, &state_message(&self.name, key, value.clone()), peer).await;
+            }
+            return;
+        }
+        let Some(command) = parse_command(action, arguments) else {
+            return;
+        };
+        let result = match command {
+            Ok(command) => self.client.execute(command).await,
+            Err(error) => Err(error),
+        };
+        let reply = match result {
+            Ok(Response::Value {
+                name: command,
+                value,
+            }) if action == "command" => Some((
+                "response",
+                vec![Argument::String(command), Argument::String(value)],
+            )),
+            Ok(_) if action == "command" => Some(("response", Vec::new())),
+            Ok(_) => None,
+            Err(error) => Some(("error", vec![Argument::String(error.to_string())])),
+        };
+        if let Some((key, arguments)) = reply {
+            let message = Message::new(format!("/projector/{}/{key}", self.name), arguments);
+            send(socket, &message, peer).await;
+        }
+        self.update(socket, peers).await
+    }
+
+    /// Polls the projector and sends the changed values to the peers
+    async fn update(&mut self, socket: &UdpSocket, peers: &SyncMutex<Peers>) {
+        let values = match state(&mut self.client).await {
+            Ok(mut values) => {
+                values.push(("online", Argument::Int(1)));
+                values
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::IO(_)) => {
+                vec![("online", Argument::Int(0))]
+            }
+            Err(_) => return,
+        };
+        for (key, value) in values {
+            if self.state.get(key) == Some(&value) {
+                continue;
+            }
+            let message = state_message(&self.name, key, value.clone());
+            let peers = peers
+                .lock()
+                .expect("no panic while locked")
+                .current(Instant::now());
+            for peer in peers {
+                send(socket, &message, peer).await;
+            }
+            self.state.insert(key, value);
+        }
+    }
+}
+
+/// Sends a message, ignoring errors as peers may be gone without the bridge being at fault
+async fn send(socket: &UdpSocket, message: &Message, peer: SocketAddr) {
+    let _ = socket.send_to(&message.encode(), peer).await;
+}
+
+/// Projector name and action of a `/projector/{name}/{action}` address
+fn route(address: &str) -> Option<(&str, &str)> {
+    address
+        .strip_prefix("/projector/")
+        .and_then(|rest| rest.split_once('/'))
+}
+
+fn state_message(name: &str, key: &str, value: Argument) -> Message {
+    Message::new(format!("/projector/{name}/{key}"), vec![value])
+}
+
+/// Name usable in OSC addresses, replacing the reserved characters with `_`
+pub fn address_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| match c {
+            ' ' | '#' | '*' | ',' | '/' | '?' | '[' | ']' | '{' | '}' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// State values of a projector, source and mute being omitted when unavailable
+pub async fn state(
+    client: &mut ReconnectingClient<SocketAddr>,
+) -> Result<Vec<(&'static str, Argument)>> {
+    let mut values = vec![(
+        "power",
+        Argument::String(client.get(Power).await?.to_string()),
+    )];
+    if let Ok(source) = client.get(SelectSource).await {
+        values.push(("source", Argument::String(source.to_string())));
+    }
+    if let Ok(mute) = client.get(Mute).await {
+        values.push(("mute", Argument::Int((mute == Switch::On).into())));
+    }
+    let fault = client.get(ErrorCode).await?;
+    values.push(("fault", Argument::String(fault.description().to_string())));
+    Ok(values)
+}
+
+/// Command for a message to `/projector/{name}/{action}`, `None` for unknown actions
+pub fn parse_command(action: &str, arguments: &[Argument]) -> Option<Result<Command>> {
+    let Some(argument) = arguments.first() else {
+        return match action {
+            "power" => Some(Ok(Power.get())),
+            "mute" => Some(Ok(Mute.get())),
+            "freeze" => Some(Ok(Freeze.get())),
+            "source" => Some(Ok(SelectSource.get())),
+            "volume" => Some(Ok(Volume.get())),
+            "command" => Some(Err(argument_error(action))),
+            _ => None,
+        };
+    };
+    let command = match action {
+        "power" => argument.to_switch().map(|switch| Power.set(switch)),
+        "mute" => argument.to_switch().map(|switch| Mute.set(switch)),
+        "freeze" => argument.to_switch().map(|switch| Freeze.set(switch)),
+        "source" => match argument {
+            Argument::String(source) => source.parse().map(|source| SelectSource.set(source)),
+            argument => argument
+                .to_int()
+                .and_then(|code| u8::try_from(code).ok())
+                .map(|code| SelectSource.set(Source::from_code(code)))
+                .ok_or_else(|| argument_error(action)),
+        },
+        "volume" => argument
+            .to_int()
+            .and_then(|volume| u8::try_from(volume).ok())
+            .map(|volume| Volume.set(volume))
+            .ok_or_else(|| argument_error(action)),
+        "command" => match argument {
+            Argument::String(command) => command.parse(),
+            _ => Err(argument_error(action)),
+        },
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn argument_error(action: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Encoding,
+        format!("Invalid argument for {action}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::testing::{silent_server, spawn_server};
+
+    #[test]
+    fn messages() {
+        let message = Message::new(
+            "/projector/hall/power".to_string(),
+            vec![Argument::String("on".to_string()), Argument::Int(1)],
+        );
+        let buf = message.encode();
+        assert_eq!(&buf[..24], b"/projector/hall/power\0\0\0");
+        assert_eq!(&buf[24..28], b",si\0");
+        assert_eq!(&buf[28..36], b"on\0\0\0\0\0\x01");
+        assert_eq!(Message::decode(&buf).unwrap(), message);
+
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        bundle.extend((buf.len() as u32).to_be_bytes());
+        bundle.extend(&buf);
+        assert_eq!(decode_packet(&bundle).unwrap(), vec![message]);
+        assert!(decode_packet(&bundle[..bundle.len() - 2]).is_err());
+        assert!(Message::decode(b"/a\0\0,x\0\0").is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            parse_command("power", &[Argument::Int(1)])
+                .unwrap()
+                .unwrap(),
+            Power.set(Switch::On)
+        );
+        assert_eq!(
+            parse_command("mute", &[Argument::String("off".to_string())])
+                .unwrap()
+                .unwrap(),
+            Mute.set(Switch::Off)
+        );
+        assert_eq!(
+            parse_command("source", &[Argument::Int(0x30)])
+                .unwrap()
+                .unwrap(),
+            SelectSource.set(Source::Hdmi1)
+        );
+        assert_eq!(parse_command("volume", &[]).unwrap().unwrap(), Volume.get());
+        assert!(parse_command("volume", &[Argument::Int(300)])
+            .unwrap()
+            .is_err());
+        assert!(parse_command("lens", &[]).is_none());
+        assert_eq!(address_name("Main Hall #2"), "Main_Hall__2");
+    }
+
+    #[test]
+    fn names() {
+        let mut bridge = Bridge::new(None, Duration::from_secs(1), Duration::from_secs(60));
+        let hall: SocketAddr = "192.168.0.10:3629".parse().unwrap();
+        let booth: SocketAddr = "192.168.0.11:3629".parse().unwrap();
+        let booth_2: SocketAddr = "192.168.0.11:3630".parse().unwrap();
+        assert_eq!(bridge.add(hall, "Main Hall"), "Main_Hall");
+        assert_eq!(bridge.add(booth, "Main Hall"), "Main_Hall_192.168.0.11");
+        assert_eq!(
+            bridge.add(booth_2, "Main Hall"),
+            "Main_Hall_192.168.0.11_3630"
+        );
+        // Adding a projector again keeps its name
+        assert_eq!(bridge.add(hall, "Main Hall"), "Main_Hall");
+        assert_eq!(bridge.projectors.len(), 3);
+    }
+
+    #[test]
+    fn peers() {
+        let now = Instant::now();
+        let mut peers = Peers::default();
+        let added: SocketAddr = "192.168.0.1:9000".parse().unwrap();
+        peers.added.push(added);
+        peers.received(added, now);
+        for port in 0..=MAX_SENDERS as u16 {
+            peers.received(SocketAddr::from(([192, 168, 0, 2], port)), now);
+        }
+        let current = peers.current(now);
+        assert_eq!(current.len(), MAX_SENDERS + 1);
+        assert_eq!(current[0], added);
+        assert_eq!(current[1], SocketAddr::from(([192, 168, 0, 2], 1)));
+
+        let later = now + SENDER_TIMEOUT / 2;
+        peers.received(SocketAddr::from(([192, 168, 0, 2], 1)), later);
+        let current = peers.current(now + SENDER_TIMEOUT);
+        assert_eq!(
+            current,
+            vec![added, SocketAddr::from(([192, 168, 0, 2], 1))]
+        );
+    }
+
+    #[tokio::test]
+    async fn bridge() {
+        let values = [("PWR", "01"), ("SOURCE", "30"), ("ERR", "00")];
+        let (projector, _) = spawn_server(&values, None).await;
+
+        let silent = silent_server().await;
+
+        let mut bridge = Bridge::new(None, Duration::from_secs(1), Duration::from_secs(60));
+        bridge.add(projector, "Main Hall");
+        bridge.add(silent, "Booth");
+        // Sending to a peer of the other address family fails
+        bridge.add_peer("[::1]:9000".parse().unwrap());
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bridge_addr = socket.local_addr().unwrap();
+        tokio::spawn(bridge.run(socket));
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let message = Message::new("/projector/Booth/power".to_string(), vec![]);
+        peer.send_to(&message.encode(), bridge_addr).await.unwrap();
+        let message = Message::new(
+            "/projector/Main_Hall/source".to_string(),
+            vec![Argument::String("hdmi2".to_string())],
+        );
+        peer.send_to(&message.encode(), bridge_addr).await.unwrap();
+        let expected = Message::new(
+            "/projector/Main_Hall/source".to_string(),
+            vec![Argument::String("Hdmi2".to_string())],
+        );
+        let mut buf = [0; BUF_SIZE];
+        // Well before the silent projector times out
+        tokio::time::timeout(Duration::from_millis(500), async {
+            loop {
+                let n = peer.recv(&mut buf).await.unwrap();
+                if Message::decode(&buf[..n]).unwrap() == expected {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        let message = Message::new("/projector/Main_Hall/command".to_string(), vec![]);
+        peer.send_to(&message.encode(), bridge_addr).await.unwrap();
+        // State changes may be received before the reply
+        loop {
+            let n = peer.recv(&mut buf).await.unwrap();
+            let reply = Message::decode(&buf[..n]).unwrap();
+            if reply.address == "/projector/Main_Hall/error" {
+                break;
+            }
+        }
+    }
+}
@@ -116,11 +116,12 @@ pub enum PacketCategory {
 
 impl Length for PacketCategory {
     const LENGTH: usize = 1;
+    type Bytes = [u8; 1];
 }
 
 impl Decode for PacketCategory {
     type Error = crate::Error;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self, Self::Error> {
+    fn decode(data: Self::Bytes) -> Result<Self, Self::Error> {
         use PacketCategory::*;
 
         match data[0] {
@@ -137,7 +138,7 @@ impl Decode for PacketCategory {
 }
 impl Encode for PacketCategory {
     type Error = crate::Error;
-    fn encode(self) -> Result<[u8; Self::LENGTH], Self::Error> {
+    fn encode(self) -> Result<Self::Bytes, Self::Error> {
         Ok([self as u8])
     }
 }
@@ -155,11 +156,12 @@ pub enum Status {
 
 impl Length for Status {
     const LENGTH: usize = 1;
+    type Bytes = [u8; 1];
 }
 
 impl Decode for Status {
     type Error = crate::Error;
-    fn decode(data: [u8; Self::LENGTH]) -> Result<Self, Self::Error> {
+    fn decode(data: Self::Bytes) -> Result<Self, Self::Error> {
         match data[0] {
             0x00 => Ok(Self::Null),
             0x20 => Ok(Self::Ok),
@@ -179,7 +181,7 @@ impl Decode for Status {
 
 impl Encode for Status {
     type Error = crate::Error;
-    fn encode(self) -> Result<[u8; Self::LENGTH], Self::Error> {
+    fn encode(self) -> Result<Self::Bytes, Self::Error> {
         Ok([self as u8])
     }
 }
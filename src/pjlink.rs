@@ -0,0 +1,573 @@
+//! PJLink client
+//!
+//! PJLink is the cross-vendor projector protocol on TCP port 4352, which many Epson
+//! projectors speak alongside ESC/VP.net. Commands are lines like `%1POWR ?\r`, answered
+//! by `%1POWR=1\r`. When the projector requires authentication, its greeting carries a
+//! random number, and the MD5 digest of this number followed by the password prefixes
+//! the first command.
+//!
+//! [`Client`] implements [`ProjectorControl`], as the ESC/VP.net client does.
+use std::{fmt, net::SocketAddr, str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufStream},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+};
+
+use crate::{
+    commands::{PowerState, Severity, Switch},
+    control::{ProjectorControl, ProjectorStatus},
+    error::ErrorKind,
+    packet::Status,
+    Result,
+};
+
+/// TCP and UDP port of PJLink
+pub const DEFAULT_PORT: u16 = 4352;
+const BUF_SIZE: usize = 1024;
+
+pub struct Client {
+    stream: BufStream<TcpStream>,
+    /// Digest to send with the next command, only the first one being authenticated
+    digest: Option<String>,
+}
+
+impl Client {
+    /// Connects and reads the greeting, failing with [`Status::Unauthorized`] if the
+    /// projector requires a password and none is given
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        password: Option<String>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        tokio::time::timeout(timeout, Self::open(addr, password))
+            .await
+            .unwrap_or_else(|_| {
+                Err(crate::Error::new(
+                    ErrorKind::IO(std::io::ErrorKind::TimedOut),
+                    "Timed out".to_string(),
+                ))
+            })
+    }
+
+    async fn open<A: ToSocketAddrs>(addr: A, password: Option<String>) -> Result<Self> {
+        let mut stream = BufStream::new(TcpStream::connect(addr).await?);
+        let greeting = read_line(&mut stream).await?;
+        let digest = match greeting.split_once(' ') {
+            Some(("PJLINK", "0")) => None,
+            Some(("PJLINK", challenge)) => match challenge.split_once(' ') {
+                Some(("1", random)) => {
+                    let password = password.ok_or_else(|| {
+                        crate::Error::new(
+                            ErrorKind::Protocol(Status::Unauthorized),
+                            "The projector requires a PJLink password".to_string(),
+                        )
+                    })?;
+                    Some(digest(random, &password))
+                }
+                _ => return Err(greeting_error(&greeting)),
+            },
+            _ => return Err(greeting_error(&greeting)),
+        };
+        Ok(Self { stream, digest })
+    }
+
+    /// Broadcasts a class 2 search and returns the address and MAC address of the
+    /// projectors answering
+    pub async fn search<A: ToSocketAddrs>(
+        bind_addr: A,
+        broadcast_addr: A,
+        timeout: Duration,
+    ) -> Result<Vec<(SocketAddr, String)>> {
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.set_broadcast(true)?;
+        socket.send_to(b"%2SRCH\r", broadcast_addr).await?;
+
+        let mut projectors = Vec::new();
+        let mut buf = [0; BUF_SIZE];
+        while let Ok(result) = tokio::time::timeout(timeout, socket.recv_from(&mut buf)).await {
+            let (n, addr) = result?;
+            let reply = String::from_utf8_lossy(&buf[..n]);
+            if let Some(mac) = reply.trim_end().strip_prefix("%2ACKN=") {
+                projectors.push((addr, mac.to_string()));
+            }
+        }
+        Ok(projectors)
+    }
+
+    /// Queries a command of `class`, such as `get(1, "LAMP")`
+    pub async fn get(&mut self, class: u8, command: &str) -> Result<String> {
+        self.send(class, command, "?").await
+    }
+
+    /// Sets a command of `class`, such as `set(1, "POWR", "1")`
+    pub async fn set(&mut self, class: u8, command: &str, parameter: &str) -> Result<()> {
+        match self.send(class, command, parameter).await?.as_str() {
+            "OK" => Ok(()),
+            reply => Err(crate::Error::new(
+                ErrorKind::Decoding,
+                format!("Unexpected reply to {command}: {reply}"),
+            )),
+        }
+    }
+
+    async fn send(&mut self, class: u8, command: &str, parameter: &str) -> Result<String> {
+        let digest = self.digest.take().unwrap_or_default();
+        let request = format!("{digest}%{class}{command} {parameter}\r");
+        self.stream.write_all(request.as_bytes()).await?;
+        self.stream.flush().await?;
+
+        let reply = read_line(&mut self.stream).await?;
+        if reply == "PJLINK ERRA" {
+            return Err(crate::Error::new(
+                ErrorKind::Protocol(Status::Forbidden),
+                "The PJLink password is wrong".to_string(),
+            ));
+        }
+        let value = reply
+            .strip_prefix(&format!("%{class}{command}="))
+            .ok_or_else(|| {
+                crate::Error::new(
+                    ErrorKind::Decoding,
+                    format!("Unexpected reply to {command}: {reply}"),
+                )
+            })?;
+        let message = match value {
+            "ERR1" => "Undefined command",
+            "ERR2" => "Out of parameter",
+            "ERR3" => "Unavailable time",
+            "ERR4" => "Projector failure",
+            value => return Ok(value.to_string()),
+        };
+        Err(crate::Error::new(
+            ErrorKind::Command,
+            format!("{command}: {message}"),
+        ))
+    }
+
+    pub async fn power(&mut self) -> Result<PowerState> {
+        match self.get(1, "POWR").await?.as_str() {
+            "0" => Ok(PowerState::Standby),
+            "1" => Ok(PowerState::On),
+            "2" => Ok(PowerState::Cooldown),
+            "3" => Ok(PowerState::Warmup),
+            value => Err(decoding_error("POWR", value)),
+        }
+    }
+
+    pub async fn set_power(&mut self, switch: Switch) -> Result<()> {
+        let parameter = match switch {
+            Switch::On => "1",
+            Switch::Off => "0",
+        };
+        self.set(1, "POWR", parameter).await
+    }
+
+    pub async fn input(&mut self) -> Result<Input> {
+        self.get(1, "INPT").await?.parse()
+    }
+
+    pub async fn set_input(&mut self, input: Input) -> Result<()> {
+        self.set(input.class(), "INPT", &input.to_string()).await
+    }
+
+    pub async fn av_mute(&mut self) -> Result<AvMute> {
+        self.get(1, "AVMT").await?.parse()
+    }
+
+    pub async fn set_av_mute(&mut self, mute: AvMute) -> Result<()> {
+        self.set(1, "AVMT", &mute.to_string()).await
+    }
+
+    pub async fn errors(&mut self) -> Result<ErrorStatus> {
+        self.get(1, "ERST").await?.parse()
+    }
+
+    /// Hours and state of each lamp
+    pub async fn lamps(&mut self) -> Result<Vec<(u32, bool)>> {
+        let value = self.get(1, "LAMP").await?;
+        let numbers: Vec<&str> = value.split_whitespace().collect();
+        numbers
+            .chunks(2)
+            .map(|lamp| match lamp {
+                [hours, on] => Ok((
+                    hours.parse().map_err(|_| decoding_error("LAMP", &value))?,
+                    *on == "1",
+                )),
+                _ => Err(decoding_error("LAMP", &value)),
+            })
+            .collect()
+    }
+
+    pub async fn manufacturer(&mut self) -> Result<String> {
+        self.get(1, "INF1").await
+    }
+}
+
+#[async_trait]
+impl ProjectorControl for Client {
+    type Input = Input;
+
+    async fn power(&mut self) -> Result<PowerState> {
+        Client::power(self).await
+    }
+
+    async fn set_power(&mut self, switch: Switch) -> Result<()> {
+        Client::set_power(self, switch).await
+    }
+
+    async fn input(&mut self) -> Result<Input> {
+        Client::input(self).await
+    }
+
+    async fn set_input(&mut self, input: Input) -> Result<()> {
+        Client::set_input(self, input).await
+    }
+
+    async fn status(&mut self) -> Result<ProjectorStatus> {
+        let power = self.power().await?;
+        let input = self.input().await.ok();
+        let lamp_hours = self
+            .lamps()
+            .await
+            .ok()
+            .and_then(|lamps| lamps.first().map(|(hours, _)| *hours));
+        let errors = self.errors().await?;
+        Ok(ProjectorStatus {
+            power,
+            input: input.map(|input| input.to_string()),
+            lamp_hours,
+            severity: errors.severity(),
+            faults: errors.descriptions(),
+        })
+    }
+}
+
+/// Input of `INPT`, a kind and a number such as `31` for the first digital input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Input {
+    pub kind: InputKind,
+    /// `1` to `9`, and `A` to `Z` from class 2
+    pub number: char,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputKind {
+    Rgb = 1,
+    Video = 2,
+    Digital = 3,
+    Storage = 4,
+    Network = 5,
+    Internal = 6,
+}
+
+impl Input {
+    /// PJLink class of the input, `2` for the inputs numbered by letters
+    pub fn class(&self) -> u8 {
+        if self.number.is_ascii_uppercase() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.kind as u8, self.number)
+    }
+}
+
+impl FromStr for Input {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chars = s.chars();
+        let (Some(kind), Some(number), None) = (chars.next(), chars.next(), chars.next()) else {
+            return Err(decoding_error("INPT", s));
+        };
+        let kind = match kind {
+            '1' => InputKind::Rgb,
+            '2' => InputKind::Video,
+            '3' => InputKind::Digital,
+            '4' => InputKind::Storage,
+            '5' => InputKind::Network,
+            '6' => InputKind::Internal,
+            _ => return Err(decoding_error("INPT", s)),
+        };
+        if !number.is_ascii_digit() && !number.is_ascii_uppercase() {
+            return Err(decoding_error("INPT", s));
+        }
+        Ok(Self { kind, number })
+    }
+}
+
+/// State of `AVMT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AvMute {
+    pub video: bool,
+    pub audio: bool,
+}
+
+impl fmt::Display for AvMute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.video, self.audio) {
+            (true, true) => write!(f, "31"),
+            (false, false) => write!(f, "30"),
+            (true, false) => write!(f, "11"),
+            (false, true) => write!(f, "21"),
+        }
+    }
+}
+
+/// Parses a state as answered by the projector, `30` and `31` being the usual ones
+impl FromStr for AvMute {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "11" => Ok(Self {
+                video: true,
+                audio: false,
+            }),
+            "21" => Ok(Self {
+                video: false,
+                audio: true,
+            }),
+            "31" => Ok(Self {
+                video: true,
+                audio: true,
+            }),
+            "10" | "20" | "30" => Ok(Self {
+                video: false,
+                audio: false,
+            }),
+            _ => Err(decoding_error("AVMT", s)),
+        }
+    }
+}
+
+/// Answer to `ERST`, the severity of each kind of error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorStatus {
+    pub fan: Severity,
+    pub lamp: Severity,
+    pub temperature: Severity,
+    pub cover_open: Severity,
+    pub filter: Severity,
+    pub other: Severity,
+}
+
+impl ErrorStatus {
+    fn entries(&self) -> [(&'static str, Severity); 6] {
+        [
+            ("Fan", self.fan),
+            ("Lamp", self.lamp),
+            ("Temperature", self.temperature),
+            ("Cover open", self.cover_open),
+            ("Filter", self.filter),
+            ("Other", self.other),
+        ]
+    }
+
+    /// Most severe of the errors
+    pub fn severity(&self) -> Severity {
+        self.entries()
+            .iter()
+            .map(|(_, severity)| *severity)
+            .max()
+            .unwrap_or(Severity::None)
+    }
+
+    /// Descriptions of the errors, like `Lamp warning`
+    pub fn descriptions(&self) -> Vec<String> {
+        self.entries()
+            .iter()
+            .filter_map(|(name, severity)| match severity {
+                Severity::None => None,
+                Severity::Warning => Some(format!("{name} warning")),
+                Severity::Critical => Some(format!("{name} error")),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for ErrorStatus {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let severities = s
+            .chars()
+            .map(|c| match c {
+                '0' => Ok(Severity::None),
+                '1' => Ok(Severity::Warning),
+                '2' => Ok(Severity::Critical),
+                _ => Err(decoding_error("ERST", s)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let [fan, lamp, temperature, cover_open, filter, other] = severities[..] else {
+            return Err(decoding_error("ERST", s));
+        };
+        Ok(Self {
+            fan,
+            lamp,
+            temperature,
+            cover_open,
+            filter,
+            other,
+        })
+    }
+}
+
+/// Authentication digest, the MD5 of the random number followed by the password
+fn digest(random: &str, password: &str) -> String {
+    format!("{:x}", md5::compute(format!("{random}{password}")))
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> Result<String> {
+    let mut line = Vec::new();
+    if stream.read_until(b'\r', &mut line).await? == 0 {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(String::from_utf8(line)?.trim_end().to_string())
+}
+
+fn greeting_error(greeting: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Decoding,
+        format!("Unexpected PJLink greeting: {greeting}"),
+    )
+}
+
+fn decoding_error(command: &str, value: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::Decoding,
+        format!("Failed to decode {command} value: {value}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const RANDOM: &str = "498e4a67";
+    const PASSWORD: &str = "JBMIAProjectorLink";
+
+    /// Projector with the password of the PJLink specification, answering one session
+    async fn spawn_projector() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream
+                .write_all(format!("PJLINK 1 {RANDOM}\r").as_bytes())
+                .await
+                .unwrap();
+            let mut authenticated = false;
+            loop {
+                let mut line = Vec::new();
+                if stream.read_until(b'\r', &mut line).await.unwrap() == 0 {
+                    return;
+                }
+                let line = String::from_utf8(line).unwrap();
+                let line = line.trim_end();
+                let line = match line.strip_prefix("5d8409bc1c3fa39749434aa3a5c38682") {
+                    Some(line) => {
+                        authenticated = true;
+                        line
+                    }
+                    None => line,
+                };
+                if !authenticated {
+                    stream.write_all(b"PJLINK ERRA\r").await.unwrap();
+                    continue;
+                }
+                let (command, parameter) = line.split_once(' ').unwrap();
+                let value = match (command, parameter) {
+                    ("%1POWR", "?") => "1",
+                    ("%1INPT", "?") => "32",
+                    // Class 1 inputs are numbered 1 to 9
+                    ("%1INPT", input) if input.ends_with(char::is_alphabetic) => "ERR2",
+                    ("%1INPT", _) | ("%2INPT", _) => "OK",
+                    ("%1ERST", "?") => "010000",
+                    ("%1LAMP", "?") => "1200 1",
+                    ("%1INF1", "?") => "EPSON",
+                    _ => "ERR1",
+                };
+                let reply = format!("{command}={value}\r");
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn authentication_digest() {
+        assert_eq!(digest(RANDOM, PASSWORD), "5d8409bc1c3fa39749434aa3a5c38682");
+    }
+
+    #[test]
+    fn values() {
+        let input: Input = "3A".parse().unwrap();
+        assert_eq!(input.kind, InputKind::Digital);
+        assert_eq!(input.to_string(), "3A");
+        assert_eq!(input.class(), 2);
+        assert_eq!("31".parse::<Input>().unwrap().class(), 1);
+        assert!("7".parse::<Input>().is_err());
+        assert_eq!(
+            "31".parse::<AvMute>().unwrap(),
+            AvMute {
+                video: true,
+                audio: true
+            }
+        );
+        let errors: ErrorStatus = "002001".parse().unwrap();
+        assert_eq!(errors.severity(), Severity::Critical);
+        assert_eq!(
+            errors.descriptions(),
+            ["Temperature error", "Other warning"]
+        );
+        assert!("0020".parse::<ErrorStatus>().is_err());
+    }
+
+    #[tokio::test]
+    async fn client() {
+        let addr = spawn_projector().await;
+        let mut client = Client::connect(addr, Some(PASSWORD.to_string()), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(client.power().await.unwrap(), PowerState::On);
+        assert_eq!(client.manufacturer().await.unwrap(), "EPSON");
+        client.set_input("31".parse().unwrap()).await.unwrap();
+        client.set_input("3A".parse().unwrap()).await.unwrap();
+        assert_eq!(
+            client.status().await.unwrap(),
+            ProjectorStatus {
+                power: PowerState::On,
+                input: Some("32".to_string()),
+                lamp_hours: Some(1200),
+                severity: Severity::Warning,
+                faults: vec!["Lamp warning".to_string()],
+            }
+        );
+        let error = client.av_mute().await.unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Command));
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let addr = spawn_projector().await;
+        let mut client = Client::connect(addr, Some("guest".to_string()), Duration::from_secs(1))
+            .await
+            .unwrap();
+        let error = client.power().await.unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Protocol(Status::Forbidden)
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! Power state machine
+//!
+//! Projectors spend tens of seconds warming up after `PWR ON` and cooling down after `PWR OFF`,
+//! rejecting most commands meanwhile. [`PowerController`] tracks the `PWR?` state,
+//! waits for transitions to complete and holds back commands which would be rejected.
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+use crate::{
+    client::Client,
+    command::{Command, Response},
+    commands::{ErrorCode, Fault, Power, PowerState, Switch},
+    connection::Transport,
+    error::ErrorKind,
+    Result,
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+impl PowerState {
+    /// Whether the lamp is off and the projector can be powered on
+    pub fn is_standby(&self) -> bool {
+        matches!(self, Self::Standby | Self::NetworkStandby | Self::AvStandby)
+    }
+
+    /// Whether the projector is warming up or cooling down
+    pub fn is_transition(&self) -> bool {
+        matches!(self, Self::Warmup | Self::Cooldown)
+    }
+
+    /// Whether a state reached `self` as a target, any standby state reaching a standby target
+    fn is_reached_by(&self, state: PowerState) -> bool {
+        *self == state || (self.is_standby() && state.is_standby())
+    }
+}
+
+pub struct PowerController<S = TcpStream> {
+    client: Client<S>,
+    state: Option<PowerState>,
+    poll_interval: Duration,
+}
+
+impl<S: Transport> PowerController<S> {
+    pub fn new(client: Client<S>) -> Self {
+        Self {
+            client,
+            state: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets how often `PWR?` is queried while waiting for a state
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    pub fn client(&mut self) -> &mut Client<S> {
+        &mut self.client
+    }
+
+    pub fn into_inner(self) -> Client<S> {
+        self.client
+    }
+
+    /// Last state read from the projector, without querying it
+    pub fn last_state(&self) -> Option<PowerState> {
+        self.state
+    }
+
+    /// Queries the current state
+    pub async fn state(&mut self) -> Result<PowerState> {
+        let state = self.client.get(Power).await?;
+        self.state = Some(state);
+        Ok(state)
+    }
+
+    /// Polls the state until it reaches `target`, any standby state reaching a standby target
+    pub async fn wait_for(&mut self, target: PowerState, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let state = self.state().await?;
+                if target.is_reached_by(state) {
+                    return Ok(());
+                }
+                if state == PowerState::AbnormalStandby {
+                    return Err(self.abnormal_standby().await);
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| Err(timeout_error(&format!("power state {target}"))))
+    }
+
+    /// Powers on and waits for the end of warm-up, waiting for a cool-down to complete first
+    pub async fn power_on(&mut self, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, async {
+            match self.state().await? {
+                PowerState::On => return Ok(()),
+                PowerState::Warmup => {}
+                PowerState::Cooldown => {
+                    self.wait_for(PowerState::NetworkStandby, timeout).await?;
+                    self.client.set(Power, Switch::On).await?;
+                }
+                PowerState::AbnormalStandby => return Err(self.abnormal_standby().await),
+                _ => self.client.set(Power, Switch::On).await?,
+            }
+            self.wait_for(PowerState::On, timeout).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(timeout_error("power on")))
+    }
+
+    /// Powers off and waits for the end of cool-down, waiting for a warm-up to complete first
+    pub async fn power_off(&mut self, timeout: Duration) -> Result<()> {
+        tokio::time::timeout(timeout, async {
+            match self.state().await? {
+                state if state.is_standby() || state == PowerState::AbnormalStandby => {
+                    return Ok(())
+                }
+                PowerState::Cooldown => {}
+                PowerState::Warmup => {
+                    self.wait_for(PowerState::On, timeout).await?;
+                    self.client.set(Power, Switch::Off).await?;
+                }
+                _ => self.client.set(Power, Switch::Off).await?,
+            }
+            self.wait_for(PowerState::NetworkStandby, timeout).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(timeout_error("power off")))
+    }
+
+    /// Executes a command once the projector is on.
+    ///
+    /// Commands sent during warm-up are held until it completes, and rejected in any
+    /// other state than on. `PWR` commands should use [`Self::power_on`] and [`Self::power_off`].
+    pub async fn execute(&mut self, command: Command, timeout: Duration) -> Result<Response> {
+        match self.state().await? {
+            PowerState::On => {}
+            PowerState::Warmup => self.wait_for(PowerState::On, timeout).await?,
+            state => {
+                return Err(power_error(
+                    state,
+                    "The projector must be on to execute this command",
+                ))
+            }
+        }
+        self.client.execute(command).await
+    }
+
+    /// Error describing the fault which caused an abnormal standby
+    async fn abnormal_standby(&mut self) -> crate::Error {
+        match self.client.get(ErrorCode).await {
+            Ok(fault) if fault != Fault::None => fault.into(),
+            _ => power_error(
+                PowerState::AbnormalStandby,
+                "The projector is in abnormal standby",
+            ),
+        }
+    }
+}
+
+fn power_error(state: PowerState, message: &str) -> crate::Error {
+    crate::Error::new(ErrorKind::Power(state), message.to_string())
+}
+
+fn timeout_error(action: &str) -> crate::Error {
+    crate::Error::new(
+        ErrorKind::IO(std::io::ErrorKind::TimedOut),
+        format!("Timed out waiting for {action}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::server::{testing::spawn, Handler, Server};
+
+    /// Projector whose transitions last for a number of `PWR?` queries
+    struct Projector {
+        state: Mutex<(PowerState, usize)>,
+    }
+
+    #[async_trait]
+    impl Handler for Projector {
+        async fn handle(&self, command: Command) -> Result<Option<String>> {
+            let mut state = self.state.lock().unwrap();
+            match command {
+                Command::Get { name } if name == "PWR" => {
+                    match *state {
+                        (PowerState::Warmup, 0) => *state = (PowerState::On, 0),
+                        (PowerState::Cooldown, 0) => *state = (PowerState::NetworkStandby, 0),
+                        (_, ref mut remaining) if *remaining > 0 => *remaining -= 1,
+                        _ => {}
+                    }
+                    Ok(Some(format!("{:02X}", state.0.code())))
+                }
+                Command::Set { name, values } if name == "PWR" => {
+                    *state = match (state.0, values.join(" ").as_str()) {
+                        (s, "ON") if s.is_standby() => (PowerState::Warmup, 2),
+                        (PowerState::On, "OFF") => (PowerState::Cooldown, 2),
+                        _ => return Err(power_error(state.0, "Rejected")),
+                    };
+                    Ok(None)
+                }
+                _ if state.0 == PowerState::On => Ok(None),
+                _ => Err(power_error(state.0, "Rejected")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn power_cycle() {
+        let projector = Projector {
+            state: Mutex::new((PowerState::NetworkStandby, 0)),
+        };
+        let addr = spawn(Server::new(projector, None, None)).await;
+
+        let client = Client::connect(addr, None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let mut controller = PowerController::new(client);
+        controller.set_poll_interval(Duration::from_millis(1));
+        let timeout = Duration::from_secs(1);
+
+        let error = controller
+            .execute("SOURCE 30".parse().unwrap(), timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Power(PowerState::NetworkStandby)
+        ));
+
+        controller.power_on(timeout).await.unwrap();
+        assert_eq!(controller.last_state(), Some(PowerState::On));
+        controller
+            .execute("SOURCE 30".parse().unwrap(), timeout)
+            .await
+            .unwrap();
+
+        controller.power_off(timeout).await.unwrap();
+        assert_eq!(controller.last_state(), Some(PowerState::NetworkStandby));
+
+        controller.client().set(Power, Switch::On).await.unwrap();
+        controller
+            .execute("SOURCE 30".parse().unwrap(), timeout)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! Client surviving dropped connections
+//!
+//! Projectors close idle sessions and drop them when entering standby or rebooting.
+//! [`ReconnectingClient`] detects dead connections, connects again with an exponential
+//! backoff, and retries [`Command::Get`]s which failed because of the connection.
+//! [`Command::Set`]s are never retried, as the projector may have applied them.
+use std::time::Duration;
+
+use tokio::{net::ToSocketAddrs, time::Instant};
+
+use crate::{
+    client::Client,
+    command::{Command, Response},
+    commands::{Control, Power, Query},
+    error::ErrorKind,
+    packet::Status,
+    Result,
+};
+
+/// Delays between connection attempts, doubling from `initial` up to `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Attempts before giving up, `None` retrying forever
+    pub max_attempts: Option<u32>,
+}
+
+/// Five attempts over 7.5 seconds, so that commands to a dead projector fail
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            max_attempts: Some(5),
+        }
+    }
+}
+
+impl Backoff {
+    /// A single attempt per command, for callers polling at their own interval
+    pub const SINGLE_ATTEMPT: Self = Self {
+        initial: Duration::from_millis(500),
+        max: Duration::from_secs(30),
+        max_attempts: Some(1),
+    };
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+pub struct ReconnectingClient<A> {
+    addr: A,
+    password: Option<String>,
+    timeout: Duration,
+    backoff: Backoff,
+    keepalive: Option<Duration>,
+    client: Option<Client>,
+    last_activity: Instant,
+}
+
+impl<A: ToSocketAddrs + Clone> ReconnectingClient<A> {
+    /// Creates a client which connects on its first command.
+    ///
+    /// `timeout` applies to every connection attempt and every command.
+    pub fn new(addr: A, password: Option<String>, timeout: Duration) -> Self {
+        Self {
+            addr,
+            password,
+            timeout,
+            backoff: Backoff::default(),
+            keepalive: None,
+            client: None,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Creates a client making a single connection attempt per command, so that a
+    /// projector polled at an interval is reported unreachable and attempted again at the
+    /// next poll instead of holding up the poll with a backoff
+    pub fn polled(addr: A, password: Option<String>, timeout: Duration) -> Self {
+        let mut client = Self::new(addr, password, timeout);
+        client.set_backoff(Backoff::SINGLE_ATTEMPT);
+        client
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    /// Sets after how long without commands [`Self::keepalive`] queries `PWR?`
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) {
+        self.keepalive = keepalive;
+    }
+
+    /// Whether a connection is currently open
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Connects, unless already connected
+    pub async fn connect(&mut self) -> Result<&mut Client> {
+        if self.client.is_none() {
+            self.client = Some(self.reconnect().await?);
+        }
+        Ok(self.client.as_mut().expect("connected above"))
+    }
+
+    /// Closes the connection, the next command opening a new one
+    pub fn disconnect(&mut self) {
+        self.client = None;
+    }
+
+    /// Executes a command, reconnecting if the connection is dead.
+    ///
+    /// A [`Command::Get`] failing because of the connection is retried once on a new one.
+    pub async fn execute(&mut self, command: Command) -> Result<Response> {
+        let is_get = matches!(command, Command::Get { .. });
+        match self.try_execute(command.clone()).await {
+            Err(error) if is_get && is_connection_error(&error) => self.try_execute(command).await,
+            result => result,
+        }
+    }
+
+    /// Queries a typed command from [`crate::commands`]
+    pub async fn get<Q: Query>(&mut self, query: Q) -> Result<Q::Output> {
+        let response = self.execute(query.get()).await?;
+        crate::client::query_output::<Q>(response)
+    }
+
+    /// Sets a typed command from [`crate::commands`]
+    pub async fn set<C: Control>(&mut self, control: C, input: C::Input) -> Result<()> {
+        self.execute(control.set(input)).await?;
+        Ok(())
+    }
+
+    /// Waits until the connection has been idle for the keepalive interval, then queries
+    /// `PWR?` to keep it open. Never completes when keepalive is disabled.
+    ///
+    /// Meant to be polled in a `tokio::select!` loop alongside the commands to send.
+    /// This method is cancel safe while waiting.
+    pub async fn keepalive(&mut self) -> Result<()> {
+        let Some(interval) = self.keepalive else {
+            return std::future::pending().await;
+        };
+        tokio::time::sleep_until(self.last_activity + interval).await;
+        self.get(Power).await.map(|_| ())
+    }
+
+    async fn try_execute(&mut self, command: Command) -> Result<Response> {
+        let timeout = self.timeout;
+        let client = self.connect().await?;
+        let result = tokio::time::timeout(timeout, client.execute(command))
+            .await
+            .unwrap_or_else(|_| {
+                Err(crate::Error::new(
+                    ErrorKind::IO(std::io::ErrorKind::TimedOut),
+                    "Timed out waiting for the projector's reply".to_string(),
+                ))
+            });
+        self.last_activity = Instant::now();
+        if let Err(error) = &result {
+            if is_connection_error(error) {
+                self.client = None;
+            }
+        }
+        result
+    }
+
+    async fn reconnect(&mut self) -> Result<Client> {
+        let mut attempt = 0;
+        loop {
+            match Client::connect(self.addr.clone(), self.password.clone(), self.timeout).await {
+                Ok(client) => {
+                    self.last_activity = Instant::now();
+                    return Ok(client);
+                }
+                Err(error) if !is_retryable(&error) => return Err(error),
+                Err(error) => {
+                    if self
+                        .backoff
+                        .max_attempts
+                        .map_or(false, |max_attempts| attempt + 1 >= max_attempts)
+                    {
+                        return Err(error);
+                    }
+                }
+            }
+            tokio::time::sleep(self.backoff.delay(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Whether the connection cannot be used anymore after this error
+fn is_connection_error(error: &crate::Error) -> bool {
+    matches!(error.kind(), ErrorKind::IO(_))
+}
+
+/// Whether connecting again may succeed, a wrong password never does
+fn is_retryable(error: &crate::Error) -> bool {
+    !matches!(
+        error.kind(),
+        ErrorKind::Encoding | ErrorKind::Protocol(Status::Unauthorized | Status::Forbidden)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        commands::PowerState,
+        connection::Connection,
+        packet::{Packet, PacketCategory},
+        server::testing::spawn_server,
+    };
+
+    /// Projector closing every session after answering a single command
+    async fn spawn_projector() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut connection = Connection::new(stream);
+                connection.read_packet().await.unwrap();
+                let packet = Packet::new(PacketCategory::Connect, Status::Ok, vec![]);
+                connection.write_packet(packet).await.unwrap();
+                let response = match connection.read_command().await.unwrap() {
+                    Some(Command::Get { name }) => Response::new(name, "01".to_string()),
+                    Some(Command::Set { .. }) => Response::Acknowledged,
+                    None => continue,
+                };
+                connection.write_response(response).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let addr = spawn_projector().await;
+        let mut client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        for _ in 0..3 {
+            assert_eq!(client.get(Power).await.unwrap(), PowerState::On);
+        }
+
+        // The session closed after the last query, and a set is not retried
+        let error = client.set(Power, true.into()).await.unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::IO(_)));
+        assert!(!client.is_connected());
+        client.set(Power, true.into()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let (addr, _) = spawn_server(&[], Some("admin")).await;
+
+        let mut client =
+            ReconnectingClient::new(addr, Some("guest".to_string()), Duration::from_secs(1));
+        let error = client.get(Power).await.unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Protocol(Status::Forbidden)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        client.set_backoff(Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(300),
+            max_attempts: Some(4),
+        });
+        let start = Instant::now();
+        assert!(client.connect().await.is_err());
+        // 100ms, 200ms then 300ms between the four attempts
+        assert!(start.elapsed() >= Duration::from_millis(600));
+
+        let mut client = ReconnectingClient::new(addr, None, Duration::from_secs(1));
+        let start = Instant::now();
+        assert!(client.connect().await.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(7500));
+    }
+}
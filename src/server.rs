@@ -0,0 +1,454 @@
+//! ESC/VP.net projector emulator
+//!
+//! Answers the `Connect` handshake and `Hello` discovery like a projector would,
+//! then serves ESC/VP21 commands with a [`Handler`].
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use tokio::{
+    net::{TcpListener, ToSocketAddrs, UdpSocket},
+    sync::Mutex,
+};
+
+use crate::{
+    command::{Command, Response},
+    connection::{Connection, Transport},
+    error::ErrorKind,
+    header::{Header, HeaderIdentifier},
+    io::{DecodeFrom, EncodeTo},
+    packet::{Packet, PacketCategory, Status},
+    Result,
+};
+
+const BUF_SIZE: usize = 1024;
+
+/// Serves the ESC/VP21 commands received by a [`Server`]
+#[async_trait]
+pub trait Handler: Send + Sync + 'static {
+    /// Returns the value of a [`Command::Get`], or `None` for a [`Command::Set`].
+    ///
+    /// Errors are answered with `ERR`.
+    async fn handle(&self, command: Command) -> Result<Option<String>>;
+}
+
+/// Handler storing set values and returning them on get
+#[derive(Debug, Default)]
+pub struct MemoryHandler {
+    values: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryHandler {
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Self {
+            values: Mutex::new(values),
+        }
+    }
+
+    pub async fn value(&self, name: &str) -> Option<String> {
+        self.values.lock().await.get(name).cloned()
+    }
+}
+
+#[async_trait]
+impl Handler for MemoryHandler {
+    async fn handle(&self, command: Command) -> Result<Option<String>> {
+        let mut values = self.values.lock().await;
+        match command {
+            Command::Get { name } => values.get(&name).cloned().map(Some).ok_or_else(|| {
+                crate::Error::new(ErrorKind::Command, format!("Unknown command {name}"))
+            }),
+            Command::Set {
+                name,
+                values: arguments,
+            } => {
+                values.insert(name, arguments.join(" "));
+                Ok(None)
+            }
+        }
+    }
+}
+
+pub struct Server<H> {
+    handler: Arc<H>,
+    name: Option<String>,
+    password: Arc<RwLock<Option<String>>>,
+}
+
+impl<H> Clone for Server<H> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            name: self.name.clone(),
+            password: self.password.clone(),
+        }
+    }
+}
+
+impl<H: Handler> Server<H> {
+    pub fn new(handler: H, name: Option<String>, password: Option<String>) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            name,
+            password: Arc::new(RwLock::new(password)),
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Current password, which clients may change with a `Password` packet
+    pub fn password(&self) -> Option<String> {
+        self.password.read().unwrap().clone()
+    }
+
+    /// Binds TCP and UDP on `addr` and serves connections and discovery until an I/O error occurs
+    pub async fn serve<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let socket = UdpSocket::bind(listener.local_addr()?).await?;
+        let server = self.clone();
+        let discovery = tokio::spawn(async move { server.serve_discovery(socket).await });
+        let result = self.serve_connections(listener).await;
+        discovery.abort();
+        result
+    }
+
+    /// Accepts connections, each one being served in its own task
+    pub async fn serve_connections(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move { server.serve_connection(stream).await });
+        }
+    }
+
+    /// Answers `Hello` packets with the projector name
+    pub async fn serve_discovery(&self, socket: UdpSocket) -> Result<()> {
+        let mut buf = [0; BUF_SIZE];
+        loop {
+            let (n, addr) = socket.recv_from(&mut buf).await?;
+            match Packet::decode_from(&mut Pin::new(&mut &buf[..n])).await {
+                Ok(packet) if packet.category == PacketCategory::Hello => {}
+                _ => continue,
+            }
+            let mut reply = Vec::new();
+            self.hello_reply()
+                .encode_to(&mut Pin::new(&mut reply))
+                .await?;
+            socket.send_to(&reply, addr).await?;
+        }
+    }
+
+    /// Runs the handshake, then serves commands until the client disconnects
+    pub async fn serve_connection<S: Transport>(&self, stream: S) -> Result<()> {
+        let mut connection = Connection::new(stream);
+
+        let packet = connection.read_packet().await?;
+        let status = match packet.category {
+            PacketCategory::Connect => self.authorize(&packet),
+            PacketCategory::Password => self.change_password(&packet),
+            _ => Status::BadRequest,
+        };
+        let connected = packet.category == PacketCategory::Connect && status == Status::Ok;
+        connection
+            .write_packet(Packet::new(packet.category, status, vec![]))
+            .await?;
+        if !connected {
+            return Ok(());
+        }
+        self.serve_commands(connection).await
+    }
+
+    /// Serves commands without handshake until the client disconnects, like a projector
+    /// on its serial port
+    pub async fn serve_raw<S: Transport>(&self, stream: S) -> Result<()> {
+        self.serve_commands(Connection::new(stream)).await
+    }
+
+    async fn serve_commands<S: Transport>(&self, mut connection: Connection<S>) -> Result<()> {
+        loop {
+            let command = match connection.read_command().await {
+                Ok(Some(command)) => command,
+                Ok(None) => return Ok(()),
+                Err(error) if matches!(error.kind(), ErrorKind::Decoding) => {
+                    connection.write_response(Response::Error).await?;
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            let name = command.name().to_string();
+            let response = match self.handler.handle(command).await {
+                Ok(Some(value)) => Response::new(name, value),
+                Ok(None) => Response::Acknowledged,
+                Err(_) => Response::Error,
+            };
+            connection.write_response(response).await?;
+        }
+    }
+
+    fn authorize(&self, packet: &Packet) -> Status {
+        let Some(password) = self.password() else {
+            return Status::Ok;
+        };
+        match packet
+            .headers()
+            .iter()
+            .find(|h| matches!(h.identifier(), HeaderIdentifier::Password))
+        {
+            None => Status::Unauthorized,
+            Some(header) if header.information() == password => Status::Ok,
+            Some(_) => Status::Forbidden,
+        }
+    }
+
+    fn change_password(&self, packet: &Packet) -> Status {
+        let status = self.authorize(packet);
+        if status != Status::Ok {
+            return status;
+        }
+        let Some(header) = packet
+            .headers()
+            .iter()
+            .find(|h| matches!(h.identifier(), HeaderIdentifier::NewPassword))
+        else {
+            return Status::BadRequest;
+        };
+        *self.password.write().unwrap() = match header.attribute() {
+            0 => None,
+            _ => Some(header.information().to_string()),
+        };
+        Status::Ok
+    }
+
+    fn hello_reply(&self) -> Packet {
+        let mut headers = vec![];
+        // Names too long for a header are truncated, and names that are not ASCII left out
+        if let Some(name) = &self.name {
+            let name = name.chars().take(Header::INFORMATION_LENGTH).collect();
+            if let Ok(header) = Header::new(HeaderIdentifier::ProjectorName, 0, name) {
+                headers.push(header);
+            }
+        }
+        Packet::new(PacketCategory::Hello, Status::Ok, headers)
+    }
+}
+
+/// Emulated projectors for the tests of every module
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::net::SocketAddr;
+
+    use tokio::net::TcpListener;
+
+    use super::{Connection, Handler, MemoryHandler, Packet, PacketCategory, Server, Status};
+
+    /// Server with a [`MemoryHandler`] holding `values`
+    pub(crate) fn memory_server(
+        values: &[(&str, &str)],
+        password: Option<&str>,
+    ) -> Server<MemoryHandler> {
+        let values = values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Server::new(
+            MemoryHandler::new(values),
+            None,
+            password.map(str::to_string),
+        )
+    }
+
+    /// Serves connections on a local port, returning its address
+    pub(crate) async fn spawn<H: Handler>(server: Server<H>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve_connections(listener).await });
+        addr
+    }
+
+    /// Serves a single session on a local port, like projectors accepting only one
+    pub(crate) async fn spawn_single<H: Handler>(server: Server<H>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            server.serve_connection(stream).await
+        });
+        addr
+    }
+
+    /// Serves sessions one at a time like projectors, the first one going silent after
+    /// the handshake until the client closes it
+    pub(crate) async fn spawn_stalling<H: Handler>(server: Server<H>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut connection = Connection::new(stream);
+            connection.read_packet().await.unwrap();
+            let packet = Packet::new(PacketCategory::Connect, Status::Ok, vec![]);
+            connection.write_packet(packet).await.unwrap();
+            let mut stream = connection.into_inner();
+            let _ = tokio::io::copy(&mut stream, &mut tokio::io::sink()).await;
+            while let Ok((stream, _)) = listener.accept().await {
+                let _ = server.serve_connection(stream).await;
+            }
+        });
+        addr
+    }
+
+    /// Accepts TCP connections on a local port but never answers the handshake
+    pub(crate) async fn silent_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        addr
+    }
+
+    /// Serves a [`MemoryHandler`] holding `values` on a local port
+    pub(crate) async fn spawn_server(
+        values: &[(&str, &str)],
+        password: Option<&str>,
+    ) -> (SocketAddr, Server<MemoryHandler>) {
+        let server = memory_server(values, password);
+        (spawn(server.clone()).await, server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{testing::spawn_server, *};
+    use crate::client::Client;
+
+    #[tokio::test]
+    async fn commands() {
+        let (addr, _) = spawn_server(&[("PWR", "01")], None).await;
+        let mut client = Client::connect(addr, None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let response = client.execute("PWR?".parse().unwrap()).await.unwrap();
+        assert_eq!(response.value(), Some("01"));
+        let response = client.execute("SOURCE 30".parse().unwrap()).await.unwrap();
+        assert_eq!(response, Response::Acknowledged);
+        let response = client.execute("SOURCE?".parse().unwrap()).await.unwrap();
+        assert_eq!(response.value(), Some("30"));
+        assert!(matches!(
+            client
+                .execute("LAMP?".parse().unwrap())
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Command
+        ));
+        client.execute("ERR 04".parse().unwrap()).await.unwrap();
+        assert!(matches!(
+            client
+                .execute("LAMP?".parse().unwrap())
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Fault(crate::commands::Fault::HighTemperature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn discovery() {
+        let server = Server::new(
+            MemoryHandler::default(),
+            Some("Conference room projector".to_string()),
+            None,
+        );
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move { server.serve_discovery(socket).await });
+        for _ in 0..2 {
+            let projectors =
+                Client::discover("127.0.0.1:0", &addr.to_string(), Duration::from_millis(200))
+                    .await
+                    .unwrap();
+            assert_eq!(projectors.len(), 1);
+            assert_eq!(projectors[0].name().as_deref(), Some("Conference room "));
+        }
+    }
+
+    #[tokio::test]
+    async fn unauthorized() {
+        let (addr, _) = spawn_server(&[("PWR", "01")], Some("admin")).await;
+        let error = Client::connect(addr, None, Duration::from_secs(1))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.kind(),
+            crate::error::ErrorKind::Protocol(Status::Unauthorized)
+        ));
+        let error = Client::connect(addr, Some("projector1".to_string()), Duration::from_secs(1))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.kind(),
+            crate::error::ErrorKind::Protocol(Status::Forbidden)
+        ));
+        assert!(
+            Client::connect(addr, Some("admin".to_string()), Duration::from_secs(1))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn change_password() {
+        let password = "admin".to_string();
+        let new_password = "projector1".to_string();
+        let (addr, server) = spawn_server(&[("PWR", "01")], Some(&password)).await;
+
+        let error = Client::change_password(
+            addr,
+            Some(new_password.clone()),
+            Some(password.clone()),
+            Duration::from_secs(1),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.kind(),
+            crate::error::ErrorKind::Protocol(Status::Forbidden)
+        ));
+
+        Client::change_password(
+            addr,
+            Some(password),
+            Some(new_password.clone()),
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(server.password(), Some(new_password.clone()));
+        assert!(
+            Client::connect(addr, Some(new_password.clone()), Duration::from_secs(1))
+                .await
+                .is_ok()
+        );
+
+        Client::change_password(addr, Some(new_password), None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(server.password(), None);
+        assert!(Client::connect(addr, None, Duration::from_secs(1))
+            .await
+            .is_ok());
+    }
+}